@@ -0,0 +1,169 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+struct AtmosphereMaterial {
+    rayleigh_scattering: vec3<f32>;
+    rayleigh_scale_height: f32;
+    mie_scattering: vec3<f32>;
+    mie_scale_height: f32;
+    mie_absorption: vec3<f32>;
+    planet_radius: f32;
+    mie_asymmetry: vec3<f32>;
+    atmosphere_radius: f32;
+    sun_direction: vec3<f32>;
+    sun_intensity: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> material: AtmosphereMaterial;
+
+[[group(2), binding(0)]]
+var<uniform> mesh: Mesh;
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+};
+
+let PI: f32 = 3.14159265358979;
+let VIEW_SAMPLES: i32 = 16;
+let LIGHT_SAMPLES: i32 = 8;
+let NO_HIT: vec2<f32> = vec2<f32>(1e30, -1e30);
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_position = mesh.model * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+// Distances along the ray to where it enters and leaves a sphere centered on
+// the origin, NO_HIT if it misses it.
+fn ray_sphere(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> vec2<f32> {
+    let b = dot(origin, direction);
+    let distance = length(origin);
+    // Factored to keep the precision near the surface of a planet sized sphere.
+    let c = (distance - radius) * (distance + radius);
+    let discriminant = b * b - c;
+    if (discriminant < 0.0) {
+        return NO_HIT;
+    }
+    let root = sqrt(discriminant);
+    return vec2<f32>(-b - root, -b + root);
+}
+
+// Rayleigh and Mie densities, relative to the ground, at a point.
+fn densities(position: vec3<f32>) -> vec2<f32> {
+    let height = length(position) - material.planet_radius;
+    return exp(-height / vec2<f32>(material.rayleigh_scale_height, material.mie_scale_height));
+}
+
+fn extinction(optical_depth: vec2<f32>) -> vec3<f32> {
+    return material.rayleigh_scattering * optical_depth.x
+        + (material.mie_scattering + material.mie_absorption) * optical_depth.y;
+}
+
+// Optical depth from a point to the sun, or a negative value in the shadow of the planet.
+fn light_optical_depth(position: vec3<f32>, sun_direction: vec3<f32>) -> vec2<f32> {
+    let ground = ray_sphere(position, sun_direction, material.planet_radius);
+    if (ground.x > 0.0) {
+        return vec2<f32>(-1.0);
+    }
+
+    let step = ray_sphere(position, sun_direction, material.atmosphere_radius).y
+        / f32(LIGHT_SAMPLES);
+    var optical_depth = vec2<f32>(0.0);
+    for (var i = 0; i < LIGHT_SAMPLES; i = i + 1) {
+        let sample = position + sun_direction * (f32(i) + 0.5) * step;
+        optical_depth = optical_depth + densities(sample) * step;
+    }
+    return optical_depth;
+}
+
+fn rayleigh_phase(cosine: f32) -> f32 {
+    return 3.0 / (16.0 * PI) * (1.0 + cosine * cosine);
+}
+
+// Cornette-Shanks phase function, with a different asymmetry per channel.
+fn mie_phase(cosine: f32, g: vec3<f32>) -> vec3<f32> {
+    let g2 = g * g;
+    let denominator = (2.0 + g2) * pow(1.0 + g2 - 2.0 * g * cosine, vec3<f32>(1.5));
+    return 3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + cosine * cosine) / denominator;
+}
+
+struct FragmentInput {
+    [[location(0)]] world_position: vec3<f32>;
+};
+
+// The depth is the one of the point where the view ray enters the atmosphere,
+// so the haze is hidden by what is in front of it but not by the ground.
+struct FragmentOutput {
+    [[builtin(frag_depth)]] depth: f32;
+    [[location(0)]] color: vec4<f32>;
+};
+
+// Depth of a point of the view ray, the nearest one for the camera itself.
+fn view_depth(point: vec3<f32>, distance: f32) -> f32 {
+    if (distance <= 0.0) {
+        return 1.0;
+    }
+    let clip_position = view.view_proj * vec4<f32>(point, 1.0);
+    return clamp(clip_position.z / clip_position.w, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fragment(in: FragmentInput) -> FragmentOutput {
+    let center = mesh.model[3].xyz;
+    let origin = view.world_position - center;
+    let direction = normalize(in.world_position - view.world_position);
+
+    let atmosphere = ray_sphere(origin, direction, material.atmosphere_radius);
+    // The ground is taken below the camera when it is in a depression.
+    let ground_radius = min(material.planet_radius, length(origin) - 1.0);
+    let ground = ray_sphere(origin, direction, ground_radius);
+    let start = max(atmosphere.x, 0.0);
+    var end = atmosphere.y;
+    if (ground.x > 0.0) {
+        end = min(end, ground.x);
+    }
+    var out: FragmentOutput;
+    out.depth = view_depth(view.world_position + direction * start, start);
+    if (end <= start) {
+        out.color = vec4<f32>(0.0);
+        return out;
+    }
+
+    let step = (end - start) / f32(VIEW_SAMPLES);
+    var optical_depth = vec2<f32>(0.0);
+    var rayleigh = vec3<f32>(0.0);
+    var mie = vec3<f32>(0.0);
+    for (var i = 0; i < VIEW_SAMPLES; i = i + 1) {
+        let position = origin + direction * (start + (f32(i) + 0.5) * step);
+        let density = densities(position) * step;
+        optical_depth = optical_depth + density;
+
+        let sun_direction = normalize(material.sun_direction);
+        let light_depth = light_optical_depth(position, sun_direction);
+        if (light_depth.x >= 0.0) {
+            let attenuation = exp(-extinction(optical_depth + light_depth));
+            let cosine = dot(direction, sun_direction);
+            rayleigh = rayleigh + density.x * attenuation * rayleigh_phase(cosine);
+            mie = mie + density.y * attenuation * mie_phase(cosine, material.mie_asymmetry);
+        }
+    }
+
+    let light = material.sun_intensity
+        * (rayleigh * material.rayleigh_scattering + mie * material.mie_scattering);
+    let transmittance = exp(-extinction(optical_depth));
+    let coverage = 1.0 - dot(transmittance, vec3<f32>(1.0 / 3.0));
+    // Exposed like a camera would, the color is premultiplied by the coverage.
+    out.color = vec4<f32>(1.0 - exp(-light), coverage);
+    return out;
+}
@@ -5,10 +5,19 @@ struct PlanetMaterial {
     max_altitude: f32;
     min_altitude: f32;
     color_mode: u32;
+    has_normal_map: u32;
 };
 
 [[group(1), binding(0)]]
 var<uniform> material: PlanetMaterial;
+[[group(1), binding(1)]]
+var color_ramp_texture: texture_1d<f32>;
+[[group(1), binding(2)]]
+var color_ramp_sampler: sampler;
+[[group(1), binding(3)]]
+var normal_map_texture: texture_2d<f32>;
+[[group(1), binding(4)]]
+var normal_map_sampler: sampler;
 
 [[group(2), binding(0)]]
 var<uniform> mesh: Mesh;
@@ -17,84 +26,92 @@ struct Vertex {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] normal: vec3<f32>;
     [[location(2)]] altitude: f32;
+    [[location(3)]] uv: vec2<f32>;
+    [[location(4)]] tangent: vec4<f32>;
+    [[location(5)]] subdivision_level: f32;
 };
 
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
-    [[location(0)]] color: vec3<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_tangent: vec4<f32>;
+    [[location(3)]] uv: vec2<f32>;
+    [[location(4)]] normalized_altitude: f32;
+    [[location(5)]] subdivision_level: f32;
 };
 
-let BLUE = vec3<f32>(0.0, 0.0, 0.4);
-let CYAN = vec3<f32>(0.0, 0.8, 0.8);
-let GREEN = vec3<f32>(0.0, 0.9, 0.0);
-let YELLOW = vec3<f32>(0.8, 0.8, 0.0);
-let RED = vec3<f32>(0.9, 0.0, 0.0);
-let BROWN = vec3<f32>(0.63, 0.32, 0.18);
-let WHITE = vec3<f32>(0.9, 0.9, 0.9);
-
-fn get_altitude_color(normalized_altitude: f32) -> vec3<f32> {
-    if (normalized_altitude < 0.15) {
-        return mix(BLUE, CYAN, normalized_altitude / 0.15);
-    } else if (normalized_altitude < 0.3) {
-        return mix(CYAN, GREEN, (normalized_altitude - 0.15) / 0.15);
-    } else if (normalized_altitude < 0.45) {
-        return mix(GREEN, YELLOW, (normalized_altitude - 0.3) / 0.15);
-    } else if (normalized_altitude < 0.6) {
-        return mix(YELLOW, RED, (normalized_altitude - 0.45) / 0.15);
-    } else if (normalized_altitude < 0.8) {
-        return mix(RED, BROWN, (normalized_altitude - 0.6) / 0.2);
-    } else {
-        return mix(BROWN, WHITE, (normalized_altitude - 0.8) / 0.2);
-    }
-}
-
-fn get_color(vertex: Vertex, world_position: vec4<f32>) -> vec3<f32> {
-    switch(material.color_mode) {
-        case 1: {
-            let world_normal = mat3x3<f32>(
-                mesh.inverse_transpose_model[0].xyz,
-                mesh.inverse_transpose_model[1].xyz,
-                mesh.inverse_transpose_model[2].xyz
-            ) * vertex.normal;
-            return (world_normal + 1.0) / 2.0;
-        }
-        case 2: {
-            let normalized_position = normalize(vertex.position);
-            let inclination = 1.0 - dot(normalized_position, vertex.normal);
-            return vec3<f32>(0.8, 0.0, inclination);
-        }
-        case 3: {
-            let normalized_altitude = (vertex.altitude - material.min_altitude) 
-                / (material.max_altitude - material.min_altitude);
-            return get_altitude_color(normalized_altitude);
-        }
-        default: {
-            return vec3<f32>(0.0);
-        }
-    }
-}
-
 [[stage(vertex)]]
 fn vertex(vertex: Vertex) -> VertexOutput {
     let world_position = mesh.model * vec4<f32>(vertex.position, 1.0);
-    let normalized_position = normalize(vertex.position);
+    let inverse_transpose_model = mat3x3<f32>(
+        mesh.inverse_transpose_model[0].xyz,
+        mesh.inverse_transpose_model[1].xyz,
+        mesh.inverse_transpose_model[2].xyz
+    );
+    let model = mat3x3<f32>(
+        mesh.model[0].xyz,
+        mesh.model[1].xyz,
+        mesh.model[2].xyz
+    );
 
     var out: VertexOutput;
     out.clip_position = view.view_proj * world_position;
-    out.color = get_color(vertex, world_position);
+    out.world_position = world_position.xyz;
+    out.world_normal = inverse_transpose_model * vertex.normal;
+    out.world_tangent = vec4<f32>(model * vertex.tangent.xyz, vertex.tangent.w);
+    out.uv = vertex.uv;
+    out.normalized_altitude = (vertex.altitude - material.min_altitude)
+        / (material.max_altitude - material.min_altitude);
+    out.subdivision_level = vertex.subdivision_level;
 
     return out;
 }
 
-
-
-
-
 struct FragmentInput {
-    [[location(0)]] color: vec3<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_tangent: vec4<f32>;
+    [[location(3)]] uv: vec2<f32>;
+    [[location(4)]] normalized_altitude: f32;
+    [[location(5)]] subdivision_level: f32;
 };
 
+fn get_normal(in: FragmentInput) -> vec3<f32> {
+    let normal = normalize(in.world_normal);
+    if (material.has_normal_map == 0u) {
+        return normal;
+    }
+
+    let tangent = normalize(in.world_tangent.xyz - normal * dot(in.world_tangent.xyz, normal));
+    let bitangent = in.world_tangent.w * cross(normal, tangent);
+    let tangent_normal = textureSample(normal_map_texture, normal_map_sampler, in.uv).rgb * 2.0 - 1.0;
+    return normalize(mat3x3<f32>(tangent, bitangent, normal) * tangent_normal);
+}
+
 [[stage(fragment)]]
 fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    let normal = get_normal(in);
+
+    switch(material.color_mode) {
+        case 1: {
+            return vec4<f32>((normal + 1.0) / 2.0, 1.0);
+        }
+        case 2: {
+            let up = normalize(in.world_position - mesh.model[3].xyz);
+            let inclination = 1.0 - dot(up, normal);
+            return vec4<f32>(0.8, 0.0, inclination, 1.0);
+        }
+        case 3: {
+            let altitude = clamp(in.normalized_altitude, 0.0, 1.0);
+            return textureSample(color_ramp_texture, color_ramp_sampler, altitude);
+        }
+        case 4: {
+            // The corners of the coarsest patches get the first color of the ramp.
+            return textureSample(color_ramp_texture, color_ramp_sampler, in.subdivision_level);
+        }
+        default: {
+            return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        }
+    }
 }
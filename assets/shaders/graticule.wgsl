@@ -0,0 +1,76 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+struct GraticuleMaterial {
+    spacing: f32;
+    line_width: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> material: GraticuleMaterial;
+
+[[group(2), binding(0)]]
+var<uniform> mesh: Mesh;
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] local_position: vec3<f32>;
+};
+
+let PI: f32 = 3.14159265358979;
+let LINE_COLOR = vec4<f32>(0.9, 0.9, 0.9, 0.5);
+let REFERENCE_COLOR = vec4<f32>(1.0, 0.8, 0.2, 0.9);
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_position = mesh.model * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * world_position;
+    out.local_position = vertex.position;
+    return out;
+}
+
+// Coverage of a line at every multiple of `spacing`, `width` pixels wide.
+fn line_coverage(coord: f32, coord_width: f32, spacing: f32, width: f32) -> f32 {
+    let distance = abs(fract(coord / spacing + 0.5) - 0.5) * spacing;
+    return 1.0 - smoothStep(0.5 * width * coord_width, width * coord_width, distance);
+}
+
+struct FragmentInput {
+    [[location(0)]] local_position: vec3<f32>;
+};
+
+[[stage(fragment)]]
+fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    // Same convention as HeightMap::get_spherical_coord: north pole at +Y,
+    // east longitude in [0, 360) starting at -Z.
+    let direction = normalize(in.local_position);
+    let latitude = asin(clamp(direction.y, -1.0, 1.0)) * 180.0 / PI;
+    let longitude = (atan2(direction.x, direction.z) + PI) * 180.0 / PI;
+
+    // The longitude wraps around on the prime meridian, take the derivative
+    // of a copy shifted by half a turn there.
+    let shifted_longitude = fract(longitude / 360.0 + 0.5) * 360.0;
+    let latitude_width = fwidth(latitude);
+    let longitude_width = min(fwidth(longitude), fwidth(shifted_longitude));
+
+    let width = material.line_width;
+    let grid = max(
+        line_coverage(latitude, latitude_width, material.spacing, width),
+        line_coverage(longitude, longitude_width, material.spacing, width),
+    );
+    let reference = max(
+        line_coverage(latitude, latitude_width, 180.0, 2.0 * width),
+        line_coverage(shifted_longitude - 180.0, longitude_width, 360.0, 2.0 * width),
+    );
+
+    if (reference > 0.0) {
+        return vec4<f32>(REFERENCE_COLOR.rgb, REFERENCE_COLOR.a * reference);
+    }
+    return vec4<f32>(LINE_COLOR.rgb, LINE_COLOR.a * grid);
+}
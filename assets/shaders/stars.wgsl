@@ -0,0 +1,44 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+struct StarsMaterial {
+    brightness: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> material: StarsMaterial;
+
+[[group(2), binding(0)]]
+var<uniform> mesh: Mesh;
+
+struct Vertex {
+    [[location(0)]] direction: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    // A null w only keeps the rotation of the model and the view, the stars
+    // stay infinitely far whatever the camera position.
+    let world_direction = mesh.model * vec4<f32>(vertex.direction, 0.0);
+    let clip_position = view.view_proj * world_direction;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(clip_position.xy, 0.0, clip_position.w);
+    out.color = vertex.color;
+    return out;
+}
+
+struct FragmentInput {
+    [[location(0)]] color: vec4<f32>;
+};
+
+[[stage(fragment)]]
+fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(in.color.rgb * material.brightness, 1.0);
+}
@@ -1,62 +1,127 @@
+mod orbit;
 mod planet;
 mod player;
+mod stars;
+mod sun;
 
-use bevy::{prelude::*, window::exit_on_window_close_system};
+use bevy::{
+    input::InputSystem,
+    pbr::{wireframe::WireframePlugin, DirectionalLightShadowMap},
+    prelude::*,
+    window::exit_on_window_close_system,
+};
+use orbit::{spawn_orbit_paths, update_orbits};
 use planet::{
-    graphics::PlanetMaterial,
+    altitude_range::{altitude_range_control, update_altitude_range},
+    atmosphere::{spawn_atmosphere, update_atmosphere},
+    catalog::{body_selection_control, setup_bodies, spawn_bodies, BodyCatalog, BodyCatalogLoader},
+    color_ramp::{ColorRamp, ColorRampAssetLoader, ColorRamps},
+    debug::{setup_mesh_debug, update_mesh_statistics, update_wireframe, wireframe_control},
+    detail::procedural_detail_control,
+    geotiff::GeoTiffHeightMapLoader,
+    graphics::{AtmosphereMaterial, GraticuleMaterial, PlanetMaterial, StarsMaterial},
+    graticule::{
+        graticule_control, setup_graticule, spawn_graticule_overlay, update_graticule,
+        update_graticule_labels,
+    },
     height_map::*,
-    view::{cycle_color_mode, update_color_mode, ColorMode},
+    legend::{setup_legend, update_legend},
+    load_errors::{setup_load_errors, update_load_errors},
+    pds::Pds3HeightMapLoader,
+    shadows::{update_shadow_projection, SHADOW_MAP_SIZE},
+    view::{
+        cycle_color_mode, cycle_color_ramp, update_albedo, update_color_mode, update_color_ramp,
+        ColorMode,
+    },
     *,
 };
 use player::*;
-
-const MARS_RADIUS: f32 = 3396000.0;
-const MARS_MIN_ALTITUDE: f32 = -8206.0;
-const MARS_MAX_ALTITUDE: f32 = 21181.0;
+use stars::{setup_stars, spawn_starfield, update_starfield, StarCatalog, StarCatalogLoader};
+use sun::{advance_clock, clock_control, date_entry_control, setup_sun, update_sun};
 
 fn main() {
+    #[allow(clippy::needless_update)]
     App::new()
         .add_plugins(DefaultPlugins)
+        .add_plugin(WireframePlugin)
         .add_plugin(MaterialPlugin::<PlanetMaterial>::default())
+        .add_plugin(MaterialPlugin::<GraticuleMaterial>::default())
+        .add_plugin(MaterialPlugin::<AtmosphereMaterial>::default())
+        .add_plugin(MaterialPlugin::<StarsMaterial>::default())
         .add_asset::<HeightMap>()
         .init_asset_loader::<HeightMapAssetLoder>()
+        .init_asset_loader::<Pds3HeightMapLoader>()
+        .init_asset_loader::<GeoTiffHeightMapLoader>()
+        .add_asset::<ColorRamp>()
+        .init_asset_loader::<ColorRampAssetLoader>()
+        .add_asset::<StarCatalog>()
+        .init_asset_loader::<StarCatalogLoader>()
+        .add_asset::<BodyCatalog>()
+        .init_asset_loader::<BodyCatalogLoader>()
         .insert_resource(ColorMode::Real)
         .insert_resource(ClearColor(Color::BLACK))
         .insert_resource(Msaa { samples: 4 })
+        .insert_resource(DirectionalLightShadowMap {
+            size: SHADOW_MAP_SIZE,
+        })
         .insert_resource(AmbientLight {
             brightness: 0.05,
             color: Color::WHITE,
             ..default()
         })
         .add_system(exit_on_window_close_system)
+        .add_system(spawn_bodies)
+        .add_system(body_selection_control)
         .add_system(planet_loading_system)
+        .add_system(planet_built_system)
         .add_system(planet_added_system)
+        .add_system(focus_control)
         .add_system(player_control)
         .add_system(cycle_color_mode)
         .add_system(update_color_mode)
+        .add_system(update_albedo)
+        .add_system(cycle_color_ramp)
+        .add_system(update_color_ramp)
+        .add_system(altitude_range_control)
+        .add_system(update_altitude_range)
+        .add_system(update_legend)
+        .add_system(spawn_graticule_overlay)
+        .add_system(graticule_control)
+        .add_system(update_graticule)
+        .add_system(update_graticule_labels)
+        .add_system(procedural_detail_control)
+        .add_system(spawn_atmosphere)
+        .add_system(update_atmosphere)
+        .add_system(clock_control)
+        // Takes the keys typed into a date before the other systems see them.
+        .add_system_to_stage(CoreStage::PreUpdate, date_entry_control.after(InputSystem))
+        .add_system(advance_clock)
+        .add_system(update_sun)
+        .add_system(update_shadow_projection)
+        .add_system(spawn_starfield)
+        .add_system(update_starfield)
+        .add_system(spawn_orbit_paths)
+        .add_system(update_orbits)
+        .add_system(wireframe_control)
+        .add_system(update_wireframe)
+        .add_system(update_mesh_statistics)
+        .add_system(update_load_errors)
         .add_startup_system(setup)
+        .add_startup_system(setup_bodies)
         .add_startup_system(setup_player)
+        .add_startup_system(setup_legend)
+        .add_startup_system(setup_graticule)
+        .add_startup_system(setup_sun)
+        .add_startup_system(setup_stars)
+        .add_startup_system(setup_mesh_debug)
+        .add_startup_system(setup_load_errors)
         .run();
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let height_map_handler = asset_server.load("mars/megt90n000fb.img");
-    commands.spawn().insert(Planet::new(
-        MARS_RADIUS,
-        MARS_MIN_ALTITUDE,
-        MARS_MAX_ALTITUDE,
-        height_map_handler,
-    ));
-
-    commands.spawn_bundle(PointLightBundle {
-        transform: Transform::from_translation(Vec3::ONE * 4.0 * MARS_RADIUS)
-            .looking_at(Vec3::ZERO, Vec3::Y),
-        point_light: PointLight {
-            color: Color::WHITE,
-            range: MARS_RADIUS * 10.0,
-            intensity: 10000000000000000.0,
-            ..default()
-        },
-        ..default()
-    });
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    commands.insert_resource(ColorRamps::new(&asset_server, &mut images));
 }
@@ -0,0 +1,201 @@
+use crate::{planet::height_map::body_axes, sun::MarsClock};
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::mesh::PrimitiveTopology,
+};
+use serde::Deserialize;
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+const J2000: f64 = 2451545.0;
+const DAYS_PER_YEAR: f64 = 365.25;
+const KEPLER_ITERATIONS: usize = 5;
+const PATH_SEGMENTS: usize = 256;
+const PATH_COLOR: Color = Color::rgba(0.6, 0.7, 0.9, 0.5);
+
+/// Orientation of a body in the ICRF, after the IAU reports: right ascension
+/// and declination of its north pole and rotation of its prime meridian, in
+/// degrees and degrees per day.
+///
+/// The scene is fixed to the body carrying it, around which the others orbit.
+#[derive(Component, Debug, Clone, Deserialize)]
+pub struct Rotation {
+    pub pole_right_ascension: f64,
+    pub pole_declination: f64,
+    pub prime_meridian: f64,
+    pub rate: f64,
+}
+
+/// Mean orbit of a moon, its angles in degrees at the J2000 epoch and measured
+/// in its Laplace plane, or of a planet around the sun, measured in the
+/// ecliptic.
+#[derive(Component, Debug, Clone, Deserialize)]
+pub struct Orbit {
+    /// In metres.
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    pub mean_anomaly: f64,
+    /// Sidereal period, in days.
+    pub period: f64,
+    /// Periods, in Julian years, of the regression of the node and of the
+    /// advance of the periapsis.
+    pub node_period: f64,
+    pub periapsis_period: f64,
+    /// Right ascension and declination of the pole of the Laplace plane.
+    pub laplace_pole: (f64, f64),
+}
+
+/// Orbit around the sun of the body the scene is fixed to, placing the sun in
+/// its sky.
+#[derive(Component, Debug, Clone)]
+pub struct HeliocentricOrbit(pub Orbit);
+
+/// Line drawn along the orbit of a moon.
+#[derive(Component)]
+pub struct OrbitPath(Entity);
+
+impl Rotation {
+    /// Rotation from the ICRF to the scene.
+    pub fn scene_from_icrf(&self, julian_date_tt: f64) -> Quat {
+        let days = julian_date_tt - J2000;
+        let rotation = (self.prime_meridian + self.rate * days).rem_euclid(360.0);
+        // The IAU rotations are passive, the opposite of the glam ones.
+        let body_from_icrf = Mat3::from_rotation_z(-rotation.to_radians() as f32)
+            * Mat3::from_rotation_x(-(FRAC_PI_2 - self.pole_declination.to_radians()) as f32)
+            * Mat3::from_rotation_z(-(FRAC_PI_2 + self.pole_right_ascension.to_radians()) as f32);
+        Quat::from_mat3(&(body_axes() * body_from_icrf))
+    }
+}
+
+impl Orbit {
+    /// Position around the primary in the ICRF, in metres.
+    pub fn position(&self, julian_date_tt: f64) -> Vec3 {
+        let days = julian_date_tt - J2000;
+        self.icrf_from_orbit(days) * self.position_in_plane(days)
+    }
+
+    /// Rotation from the plane of the orbit, with x toward the periapsis, to the
+    /// ICRF, `days` after J2000.
+    fn icrf_from_orbit(&self, days: f64) -> Quat {
+        let years = days / DAYS_PER_YEAR;
+        let node = self.ascending_node - 360.0 * years / self.node_period;
+        let periapsis = self.argument_of_periapsis + 360.0 * years / self.periapsis_period;
+        let (right_ascension, declination) = self.laplace_pole;
+        let angle = |degrees: f64| degrees.rem_euclid(360.0).to_radians() as f32;
+
+        // The Laplace plane crosses the equator of the ICRF at its ascending
+        // node, from which the node of the orbit is measured.
+        Quat::from_rotation_z(angle(right_ascension + 90.0))
+            * Quat::from_rotation_x(angle(90.0 - declination))
+            * Quat::from_rotation_z(angle(node))
+            * Quat::from_rotation_x(angle(self.inclination))
+            * Quat::from_rotation_z(angle(periapsis))
+    }
+
+    /// Position in the plane of the orbit, in metres, `days` after J2000.
+    fn position_in_plane(&self, days: f64) -> Vec3 {
+        let mean_anomaly = (self.mean_anomaly + 360.0 * days / self.period)
+            .rem_euclid(360.0)
+            .to_radians();
+        // Newton's method on Kepler's equation, quick to converge on these
+        // nearly circular orbits.
+        let mut eccentric_anomaly = mean_anomaly;
+        for _ in 0..KEPLER_ITERATIONS {
+            eccentric_anomaly -=
+                (eccentric_anomaly - self.eccentricity * eccentric_anomaly.sin() - mean_anomaly)
+                    / (1.0 - self.eccentricity * eccentric_anomaly.cos());
+        }
+        self.point_at(eccentric_anomaly)
+    }
+
+    fn point_at(&self, eccentric_anomaly: f64) -> Vec3 {
+        let semi_minor_axis = self.semi_major_axis * (1.0 - self.eccentricity.powi(2)).sqrt();
+        Vec3::new(
+            (self.semi_major_axis * (eccentric_anomaly.cos() - self.eccentricity)) as f32,
+            (semi_minor_axis * eccentric_anomaly.sin()) as f32,
+            0.0,
+        )
+    }
+
+    fn build_path(&self) -> Mesh {
+        let positions: Vec<[f32; 3]> = (0..=PATH_SEGMENTS)
+            .map(|segment| self.point_at(TAU * segment as f64 / PATH_SEGMENTS as f64))
+            .map(|point| point.into())
+            .collect();
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+}
+
+pub fn spawn_orbit_paths(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    orbits: Query<(Entity, &Orbit), Added<Orbit>>,
+) {
+    for (entity, orbit) in orbits.iter() {
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: meshes.add(orbit.build_path()),
+                material: materials.add(StandardMaterial {
+                    base_color: PATH_COLOR,
+                    unlit: true,
+                    alpha_mode: AlphaMode::Blend,
+                    ..default()
+                }),
+                ..default()
+            })
+            .insert(OrbitPath(entity))
+            .insert(NotShadowCaster)
+            .insert(NotShadowReceiver);
+    }
+}
+
+/// Moves the moons along their orbits around the body the scene is fixed to,
+/// turning the same face toward it.
+#[allow(clippy::type_complexity)]
+pub fn update_orbits(
+    clock: Res<MarsClock>,
+    primaries: Query<&Rotation>,
+    mut moons: Query<(&Orbit, &mut Transform)>,
+    mut paths: Query<(&OrbitPath, &mut Transform), Without<Orbit>>,
+    added: Query<(), Or<(Added<Orbit>, Added<OrbitPath>)>>,
+) {
+    let primary = match primaries.get_single() {
+        Ok(primary) => primary,
+        Err(_) => return,
+    };
+    if !clock.is_changed() && added.is_empty() {
+        return;
+    }
+
+    let days = clock.julian_date_tt() - J2000;
+    let scene_from_icrf = primary.scene_from_icrf(clock.julian_date_tt());
+    for (orbit, mut transform) in moons.iter_mut() {
+        let scene_from_orbit = scene_from_icrf * orbit.icrf_from_orbit(days);
+        let position = scene_from_orbit * orbit.position_in_plane(days);
+        // The prime meridian faces the primary and the north pole follows the
+        // orbit normal.
+        let toward_primary = -position.normalize();
+        let north = scene_from_orbit * Vec3::Z;
+        let axes = Mat3::from_cols(toward_primary, north.cross(toward_primary), north);
+
+        transform.translation = position;
+        transform.rotation = Quat::from_mat3(&(axes * body_axes().transpose()));
+    }
+
+    for (path, mut transform) in paths.iter_mut() {
+        if let Ok((orbit, _)) = moons.get(path.0) {
+            transform.rotation = scene_from_icrf * orbit.icrf_from_orbit(days);
+        }
+    }
+}
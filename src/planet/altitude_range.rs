@@ -0,0 +1,222 @@
+use super::{
+    graphics::{PlanetMaterial, ATTRIBUTE_ALTITUDE},
+    Planet, PlanetMaterials,
+};
+use crate::player::PlayerCamera;
+use bevy::{prelude::*, render::mesh::VertexAttributeValues};
+
+const HISTOGRAM_BINS: usize = 1024;
+const LOWER_PERCENTILE: f32 = 0.02;
+const UPPER_PERCENTILE: f32 = 0.98;
+const ADJUST_SPEED: f32 = 0.25;
+
+/// Altitude range, in metres, mapped onto the color ramp of the altitude color mode.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct AltitudeRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl AltitudeRange {
+    pub fn from_planet(planet: &Planet) -> Self {
+        Self {
+            min: planet.min_altitude,
+            max: planet.max_altitude,
+        }
+    }
+
+    fn span(&self) -> f32 {
+        self.max - self.min
+    }
+}
+
+pub fn altitude_range_control(
+    keyboard: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    camera: Query<(&GlobalTransform, &Camera), With<PlayerCamera>>,
+    meshes: Res<Assets<Mesh>>,
+    mut planets: Query<(
+        &Planet,
+        &mut AltitudeRange,
+        &GlobalTransform,
+        Option<&Handle<Mesh>>,
+    )>,
+) {
+    let step = ADJUST_SPEED * time.delta_seconds();
+
+    for (planet, mut range, transform, mesh) in planets.iter_mut() {
+        let mut new_range = *range;
+        let delta = step * range.span();
+        if keyboard.pressed(KeyCode::PageUp) {
+            new_range.max += delta;
+        }
+        if keyboard.pressed(KeyCode::PageDown) {
+            new_range.max = (new_range.max - delta).max(new_range.min + 1.0);
+        }
+        if keyboard.pressed(KeyCode::Home) {
+            new_range.min = (new_range.min + delta).min(new_range.max - 1.0);
+        }
+        if keyboard.pressed(KeyCode::End) {
+            new_range.min -= delta;
+        }
+        if keyboard.just_pressed(KeyCode::F9) {
+            new_range = AltitudeRange::from_planet(planet);
+        }
+        if keyboard.just_pressed(KeyCode::F8) {
+            let mesh = mesh.and_then(|handle| meshes.get(handle));
+            if let (Some(mesh), Ok((camera_transform, camera))) = (mesh, camera.get_single()) {
+                let model = transform.compute_matrix();
+                let view_proj =
+                    camera.projection_matrix * camera_transform.compute_matrix().inverse() * model;
+                let camera_position = model
+                    .inverse()
+                    .transform_point3(camera_transform.translation);
+                let altitudes =
+                    visible_altitudes(mesh, view_proj, camera_position, planet.height_scale);
+                match fit_range(&altitudes, AltitudeRange::from_planet(planet)) {
+                    Some(fitted) => new_range = fitted,
+                    None => info!("No terrain in view to fit the altitude range"),
+                }
+            }
+        }
+
+        if new_range != *range {
+            *range = new_range;
+        }
+    }
+}
+
+pub fn update_altitude_range(
+    mut planet_materials: ResMut<Assets<PlanetMaterial>>,
+    planets: Query<(&Planet, &AltitudeRange, &PlanetMaterials), Changed<AltitudeRange>>,
+) {
+    for (planet, range, materials) in planets.iter() {
+        if let Some(material) = planet_materials.get_mut(materials.custom.clone()) {
+            material.min_altitude = range.min * planet.height_scale;
+            material.max_altitude = range.max * planet.height_scale;
+        }
+    }
+}
+
+/// Altitudes of the mesh vertices inside the view frustum and facing the camera,
+/// with `view_proj` and `camera_position` in the mesh local space.
+fn visible_altitudes(
+    mesh: &Mesh,
+    view_proj: Mat4,
+    camera_position: Vec3,
+    height_scale: f32,
+) -> Vec<f32> {
+    let (positions, altitudes) = match (
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+        mesh.attribute(ATTRIBUTE_ALTITUDE),
+    ) {
+        (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32(altitudes)),
+        ) => (positions, altitudes),
+        _ => return vec![],
+    };
+
+    positions
+        .iter()
+        .zip(altitudes.iter())
+        .filter(|(position, _)| {
+            let position = Vec3::from(**position);
+            let clip = view_proj * position.extend(1.0);
+            let ndc = clip.truncate() / clip.w;
+            let facing = position.dot(camera_position - position) > 0.0;
+            clip.w > 0.0 && ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && facing
+        })
+        .map(|(_, altitude)| altitude / height_scale)
+        .collect()
+}
+
+/// Fits the range to the given altitudes through a histogram over `bounds`,
+/// clipping the lowest and highest percentiles to ignore outliers.
+fn fit_range(altitudes: &[f32], bounds: AltitudeRange) -> Option<AltitudeRange> {
+    if altitudes.is_empty() || bounds.span() <= 0.0 {
+        return None;
+    }
+
+    let mut histogram = vec![0_usize; HISTOGRAM_BINS];
+    for altitude in altitudes {
+        let bin = ((altitude - bounds.min) / bounds.span() * HISTOGRAM_BINS as f32) as isize;
+        histogram[bin.clamp(0, HISTOGRAM_BINS as isize - 1) as usize] += 1;
+    }
+
+    let bin_altitude = |bin: usize| bounds.min + bin as f32 / HISTOGRAM_BINS as f32 * bounds.span();
+    let lower_count = (LOWER_PERCENTILE * altitudes.len() as f32) as usize;
+    let upper_count = (UPPER_PERCENTILE * altitudes.len() as f32) as usize;
+    let mut accumulated = 0;
+    let mut min = None;
+    let mut max = bounds.max;
+    for (bin, count) in histogram.iter().enumerate() {
+        accumulated += count;
+        if min.is_none() && accumulated > lower_count {
+            min = Some(bin_altitude(bin));
+        }
+        if accumulated >= upper_count {
+            max = bin_altitude(bin + 1);
+            break;
+        }
+    }
+
+    let min = min?;
+    (max > min).then_some(AltitudeRange { min, max })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bin_width(bounds: AltitudeRange) -> f32 {
+        bounds.span() / HISTOGRAM_BINS as f32
+    }
+
+    #[test]
+    fn uniform_ramp_keeps_inner_percentiles() {
+        let bounds = AltitudeRange {
+            min: 0.0,
+            max: 1000.0,
+        };
+        let altitudes: Vec<_> = (0..10000).map(|i| i as f32 * 0.1).collect();
+
+        let range = fit_range(&altitudes, bounds).unwrap();
+        assert!((range.min - 20.0).abs() <= bin_width(bounds), "{:?}", range);
+        assert!(
+            (range.max - 980.0).abs() <= bin_width(bounds),
+            "{:?}",
+            range
+        );
+    }
+
+    #[test]
+    fn outliers_are_clipped() {
+        let bounds = AltitudeRange {
+            min: -5000.0,
+            max: 9000.0,
+        };
+        let mut altitudes: Vec<_> = (0..1000).map(|i| 100.0 + i as f32 * 0.1).collect();
+        altitudes.extend([-5000.0; 10]);
+        altitudes.extend([9000.0; 10]);
+
+        let range = fit_range(&altitudes, bounds).unwrap();
+        assert!(range.min >= 100.0 - bin_width(bounds), "{:?}", range);
+        assert!(range.max <= 200.0 + bin_width(bounds), "{:?}", range);
+    }
+
+    #[test]
+    fn empty_or_zero_span_input_has_no_range() {
+        let bounds = AltitudeRange {
+            min: 0.0,
+            max: 1000.0,
+        };
+        assert_eq!(fit_range(&[], bounds), None);
+
+        let flat = AltitudeRange {
+            min: 500.0,
+            max: 500.0,
+        };
+        assert_eq!(fit_range(&[500.0; 100], flat), None);
+    }
+}
@@ -0,0 +1,90 @@
+use super::{graphics::AtmosphereMaterial, Planet};
+use crate::sun::Sun;
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use serde::Deserialize;
+
+/// Subdivisions of the icosphere the atmosphere is drawn on.
+const SHELL_SUBDIVISIONS: usize = 5;
+
+/// Gases and dust scattering the sunlight around a planet, all the lengths in metres.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Atmosphere {
+    /// Altitude above which the atmosphere is neglected.
+    pub height: f32,
+    /// Scattering coefficients of the gas at the ground, per metre.
+    pub rayleigh_scattering: Vec3,
+    pub rayleigh_scale_height: f32,
+    /// Scattering and absorption coefficients of the aerosols at the ground, per metre.
+    pub mie_scattering: Vec3,
+    pub mie_absorption: Vec3,
+    /// How much the aerosols scatter forward, per channel.
+    pub mie_asymmetry: Vec3,
+    pub mie_scale_height: f32,
+    pub sun_intensity: f32,
+}
+
+impl Atmosphere {
+    fn material(&self, planet_radius: f32) -> AtmosphereMaterial {
+        AtmosphereMaterial {
+            rayleigh_scattering: self.rayleigh_scattering,
+            rayleigh_scale_height: self.rayleigh_scale_height,
+            mie_scattering: self.mie_scattering,
+            mie_scale_height: self.mie_scale_height,
+            mie_absorption: self.mie_absorption,
+            planet_radius,
+            mie_asymmetry: self.mie_asymmetry,
+            atmosphere_radius: planet_radius + self.height,
+            sun_direction: Vec3::Y,
+            sun_intensity: self.sun_intensity,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct AtmosphereShell;
+
+pub fn spawn_atmosphere(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<AtmosphereMaterial>>,
+    planets: Query<(Entity, &Planet), Added<Planet>>,
+) {
+    for (entity, planet) in planets.iter() {
+        if let Some(atmosphere) = &planet.atmosphere {
+            let mesh = meshes.add(Mesh::from(shape::Icosphere {
+                radius: planet.radius + atmosphere.height,
+                subdivisions: SHELL_SUBDIVISIONS,
+            }));
+            let material = materials.add(atmosphere.material(planet.radius));
+            commands.entity(entity).with_children(|children| {
+                children
+                    .spawn_bundle(MaterialMeshBundle {
+                        mesh,
+                        material,
+                        ..default()
+                    })
+                    .insert(AtmosphereShell)
+                    .insert(NotShadowCaster);
+            });
+        }
+    }
+}
+
+pub fn update_atmosphere(
+    mut materials: ResMut<Assets<AtmosphereMaterial>>,
+    suns: Query<&GlobalTransform, With<Sun>>,
+    shells: Query<&Handle<AtmosphereMaterial>, With<AtmosphereShell>>,
+) {
+    if let Some(sun) = suns.iter().next() {
+        // The light shines along its forward axis, away from the sun.
+        let sun_direction = sun.back();
+        for handle in shells.iter() {
+            let outdated = materials
+                .get(handle)
+                .map(|material| material.sun_direction != sun_direction);
+            if outdated == Some(true) {
+                materials.get_mut(handle).unwrap().sun_direction = sun_direction;
+            }
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use super::{normal_map, pyramid::Pyramid, BuiltPlanet, PlanetVertices};
+use anyhow::bail;
+use bevy::{asset::FileAssetIo, prelude::*};
+use std::{
+    cmp::Reverse,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const MAGIC: &[u8; 4] = b"PEMC";
+/// To be increased when the layout of the files or the way the meshes and the
+/// normal maps are built changes, so that they get rebuilt.
+const PLANET_VERSION: u32 = 1;
+/// Same for the pyramids of the height maps.
+const PYRAMID_VERSION: u32 = 1;
+/// Meshes kept for each body, so that going back and forth between two of
+/// its variants, like with and without the procedural detail, loads both.
+const PLANET_ENTRIES_PER_BODY: usize = 2;
+
+fn directory() -> PathBuf {
+    FileAssetIo::get_root_path().join("cache")
+}
+
+/// File holding the entry `name` of the cache.
+fn path(name: &str) -> PathBuf {
+    directory().join(format!("{}.bin", name))
+}
+
+/// Reads the entry `name` built on a previous run, `None` if there is none or
+/// it cannot be read.
+fn load<T>(name: &str, read: impl FnOnce(&mut BufReader<File>) -> anyhow::Result<T>) -> Option<T> {
+    let path = path(name);
+    let file = File::open(&path).ok()?;
+    match read(&mut BufReader::new(file)) {
+        Ok(entry) => {
+            info!("Loaded cached {:?}", path);
+            Some(entry)
+        }
+        Err(err) => {
+            warn!("Cannot read cached {:?}: {:?}", path, err);
+            None
+        }
+    }
+}
+
+fn save(name: &str, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+    let path = path(name);
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| File::create(&path))
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.flush()
+        });
+    if let Err(err) = result {
+        warn!("Cannot cache {:?}: {:?}", path, err);
+    }
+}
+
+/// Start of the names of the entries holding the meshes and normal maps of a
+/// body, followed by the key of their inputs.
+fn planet_prefix(body: &str) -> String {
+    let body: String = body
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("planet_{}_", body)
+}
+
+fn planet_entry(body: &str, key: u64) -> String {
+    format!("{}{:016x}", planet_prefix(body), key)
+}
+
+/// Whether the file `name` is an entry of the body, and not of another one
+/// whose name starts with it.
+fn is_planet_entry(name: &str, body: &str) -> bool {
+    name.strip_prefix(&planet_prefix(body))
+        .and_then(|name| name.strip_suffix(".bin"))
+        .is_some_and(|key| key.len() == 16 && key.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Mesh and normal map of the body built on a previous run from the inputs
+/// hashed in `key`.
+pub fn load_planet(body: &str, key: u64) -> Option<BuiltPlanet> {
+    let name = planet_entry(body, key);
+    let built = load(&name, |reader| read_planet(reader, key))?;
+    // The entry used last is kept over the older ones.
+    let touched = File::options()
+        .append(true)
+        .open(path(&name))
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(err) = touched {
+        warn!("Cannot touch cached {:?}: {:?}", path(&name), err);
+    }
+    Some(built)
+}
+
+/// Caches the mesh and normal map of the body, removing its entries but the
+/// newest ones so that a body rebuilt from changing inputs does not fill the
+/// disk, a mesh of 11 subdivisions taking close to a gigabyte.
+pub fn save_planet(body: &str, key: u64, built: &BuiltPlanet) {
+    save(&planet_entry(body, key), |writer| {
+        write_planet(writer, key, built)
+    });
+    evict_planets(&directory(), body, PLANET_ENTRIES_PER_BODY);
+}
+
+/// Removes the entries of the body in `directory` but the `keep` last used.
+fn evict_planets(directory: &Path, body: &str, keep: usize) {
+    let files = match fs::read_dir(directory) {
+        Ok(files) => files,
+        Err(_) => return,
+    };
+    let mut entries: Vec<(SystemTime, PathBuf)> = files
+        .filter_map(Result::ok)
+        .filter(|file| {
+            file.file_name()
+                .to_str()
+                .is_some_and(|name| is_planet_entry(name, body))
+        })
+        .filter_map(|file| Some((file.metadata().ok()?.modified().ok()?, file.path())))
+        .collect();
+    entries.sort_by_key(|(modified, _)| Reverse(*modified));
+    for (_, path) in entries.into_iter().skip(keep) {
+        match fs::remove_file(&path) {
+            Ok(()) => info!("Removed cached {:?}", path),
+            Err(err) => warn!("Cannot remove cached {:?}: {:?}", path, err),
+        }
+    }
+}
+
+/// Pyramid built on a previous run from the height map hashed in `key`.
+pub fn load_pyramid(name: &str, key: u64) -> Option<Pyramid> {
+    load(name, |reader| {
+        read_header(reader, PYRAMID_VERSION, key)?;
+        Pyramid::read(reader)
+    })
+}
+
+pub fn save_pyramid(name: &str, key: u64, pyramid: &Pyramid) {
+    save(name, |writer| {
+        write_header(writer, PYRAMID_VERSION, key)?;
+        pyramid.write(writer)
+    })
+}
+
+fn write_header(writer: &mut impl Write, version: u32, key: u64) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&version.to_le_bytes())?;
+    writer.write_all(&key.to_le_bytes())
+}
+
+fn read_header(reader: &mut impl Read, version: u32, key: u64) -> anyhow::Result<()> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("Not a cache file");
+    }
+    let file_version = u32::from_le_bytes(read_bytes(reader)?);
+    if file_version != version {
+        bail!("Cached by version {}, not {}", file_version, version);
+    }
+    if u64::from_le_bytes(read_bytes(reader)?) != key {
+        bail!("Cached for other inputs");
+    }
+    Ok(())
+}
+
+fn write_planet(writer: &mut impl Write, key: u64, built: &BuiltPlanet) -> io::Result<()> {
+    let vertices = &built.vertices;
+    let size = built.normal_map.texture_descriptor.size;
+    write_header(writer, PLANET_VERSION, key)?;
+    writer.write_all(&(vertices.positions.len() as u64).to_le_bytes())?;
+    writer.write_all(&(vertices.indices.len() as u64).to_le_bytes())?;
+    writer.write_all(&size.width.to_le_bytes())?;
+    writer.write_all(&size.height.to_le_bytes())?;
+
+    let floats = vertices
+        .positions
+        .iter()
+        .flatten()
+        .chain(vertices.normals.iter().flatten())
+        .chain(&vertices.altitudes)
+        .chain(vertices.uvs.iter().flatten())
+        .chain(vertices.tangents.iter().flatten())
+        .chain(&vertices.subdivision_levels);
+    write_values(writer, floats.map(|value| value.to_le_bytes()))?;
+    write_values(
+        writer,
+        vertices.indices.iter().map(|index| index.to_le_bytes()),
+    )?;
+    writer.write_all(&built.normal_map.data)
+}
+
+fn read_planet(reader: &mut impl Read, key: u64) -> anyhow::Result<BuiltPlanet> {
+    read_header(reader, PLANET_VERSION, key)?;
+    let vertex_count = read_count(reader)?;
+    let index_count = read_count(reader)?;
+    let width = u32::from_le_bytes(read_bytes(reader)?) as usize;
+    let height = u32::from_le_bytes(read_bytes(reader)?) as usize;
+
+    let vertices = PlanetVertices {
+        positions: read_arrays(reader, vertex_count)?,
+        normals: read_arrays(reader, vertex_count)?,
+        altitudes: read_values(reader, vertex_count, f32::from_le_bytes)?,
+        uvs: read_arrays(reader, vertex_count)?,
+        tangents: read_arrays(reader, vertex_count)?,
+        subdivision_levels: read_values(reader, vertex_count, f32::from_le_bytes)?,
+        indices: read_values(reader, index_count, u32::from_le_bytes)?,
+    };
+    let pixels = read_values(reader, width * height, |pixel| pixel)?;
+    Ok(BuiltPlanet {
+        vertices,
+        normal_map: normal_map::image(width, height, pixels.concat()),
+    })
+}
+
+pub(super) fn write_values(
+    writer: &mut impl Write,
+    values: impl Iterator<Item = [u8; 4]>,
+) -> io::Result<()> {
+    for value in values {
+        writer.write_all(&value)?;
+    }
+    Ok(())
+}
+
+pub(super) fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub(super) fn read_count(reader: &mut impl Read) -> io::Result<usize> {
+    usize::try_from(u64::from_le_bytes(read_bytes(reader)?))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Reads `count` values of four bytes, growing the buffer as they are read so
+/// that a corrupted count fails on the end of the file instead of allocating.
+pub(super) fn read_values<T>(
+    reader: &mut impl Read,
+    count: usize,
+    from_le_bytes: fn([u8; 4]) -> T,
+) -> io::Result<Vec<T>> {
+    let size = count
+        .checked_mul(4)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Too many values"))?;
+    let mut bytes = vec![];
+    reader.take(size as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| from_le_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
+
+fn read_arrays<const N: usize>(reader: &mut impl Read, count: usize) -> io::Result<Vec<[f32; N]>> {
+    let values = read_values(reader, count.saturating_mul(N), f32::from_le_bytes)?;
+    Ok(values
+        .chunks_exact(N)
+        .map(|chunk| chunk.try_into().unwrap())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn built_planet() -> BuiltPlanet {
+        BuiltPlanet {
+            vertices: PlanetVertices {
+                positions: vec![[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+                normals: vec![[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+                altitudes: vec![-1.0, 0.5, 2.0],
+                uvs: vec![[0.0, 0.5], [0.25, 0.5], [0.5, 0.0]],
+                tangents: vec![[1.0, 0.0, 0.0, 1.0]; 3],
+                subdivision_levels: vec![0.0, 0.5, 1.0],
+                indices: vec![0, 1, 2],
+            },
+            normal_map: normal_map::image(2, 1, vec![128, 128, 255, 255, 0, 64, 200, 255]),
+        }
+    }
+
+    fn planet_bytes(key: u64) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_planet(&mut bytes, key, &built_planet()).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn reads_back_the_planets_written() {
+        let built = read_planet(&mut planet_bytes(7).as_slice(), 7).unwrap();
+        let expected = built_planet();
+        assert_eq!(built.vertices.positions, expected.vertices.positions);
+        assert_eq!(built.vertices.normals, expected.vertices.normals);
+        assert_eq!(built.vertices.altitudes, expected.vertices.altitudes);
+        assert_eq!(built.vertices.uvs, expected.vertices.uvs);
+        assert_eq!(built.vertices.tangents, expected.vertices.tangents);
+        assert_eq!(
+            built.vertices.subdivision_levels,
+            expected.vertices.subdivision_levels
+        );
+        assert_eq!(built.vertices.indices, expected.vertices.indices);
+        assert_eq!(
+            built.normal_map.texture_descriptor.size,
+            expected.normal_map.texture_descriptor.size
+        );
+        assert_eq!(built.normal_map.data, expected.normal_map.data);
+    }
+
+    #[test]
+    fn rejects_truncated_planets() {
+        let bytes = planet_bytes(7);
+        for length in [0, 3, 10, 20, 40, bytes.len() - 1] {
+            assert!(read_planet(&mut &bytes[..length], 7).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_planets_with_corrupted_counts() {
+        let mut bytes = planet_bytes(7);
+        // The vertex count, right after the header.
+        bytes[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read_planet(&mut bytes.as_slice(), 7).is_err());
+    }
+
+    #[test]
+    fn names_the_planet_entries_after_their_body() {
+        assert_eq!(planet_entry("Mars", 0xab), "planet_mars_00000000000000ab");
+        assert_eq!(
+            planet_entry("../Deimos 2", 1),
+            "planet____deimos_2_0000000000000001"
+        );
+        assert!(is_planet_entry("planet_mars_00000000000000ab.bin", "Mars"));
+        assert!(!is_planet_entry(
+            "planet_mars_2_00000000000000ab.bin",
+            "Mars"
+        ));
+        assert!(!is_planet_entry("planet_mars_00000000000000ab.bin", "Moon"));
+    }
+
+    #[test]
+    fn keeps_the_entries_of_each_body_used_last() {
+        let directory = std::env::temp_dir().join(format!("planet_cache_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let start = SystemTime::now();
+        let entries = [
+            ("Mars", 1),
+            ("Mars", 2),
+            ("Mars", 3),
+            ("Mars 2", 4),
+            ("Moon", 5),
+        ];
+        for (age, (body, key)) in entries.iter().rev().enumerate() {
+            let file = File::create(directory.join(format!("{}.bin", planet_entry(body, *key))));
+            let modified = start - Duration::from_secs(age as u64 * 60);
+            file.unwrap().set_modified(modified).unwrap();
+        }
+
+        evict_planets(&directory, "Mars", 2);
+        let mut left: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(
+            left,
+            [
+                "planet_mars_0000000000000002.bin",
+                "planet_mars_0000000000000003.bin",
+                "planet_mars_2_0000000000000004.bin",
+                "planet_moon_0000000000000005.bin",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_back_the_pyramids_written() {
+        let samples: Vec<f32> = (0..48).map(|sample| sample as f32).collect();
+        let pyramid = Pyramid::build(8, 6, |index| {
+            Some(samples[index]).filter(|sample| *sample != 9.0)
+        });
+        let mut bytes = vec![];
+        pyramid.write(&mut bytes).unwrap();
+
+        let read = Pyramid::read(&mut bytes.as_slice()).unwrap();
+        let mut rewritten = vec![];
+        read.write(&mut rewritten).unwrap();
+        assert_eq!(bytes, rewritten);
+        assert_eq!(read.range(), Some((0.0, 47.0)));
+
+        assert!(Pyramid::read(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_entries_of_other_files_versions_or_inputs() {
+        let mut bytes = vec![];
+        write_header(&mut bytes, PYRAMID_VERSION, 7).unwrap();
+        assert!(read_header(&mut bytes.as_slice(), PYRAMID_VERSION, 7).is_ok());
+        assert!(read_header(&mut bytes.as_slice(), PYRAMID_VERSION, 8).is_err());
+        assert!(read_header(&mut bytes.as_slice(), PYRAMID_VERSION + 1, 7).is_err());
+
+        bytes[..4].copy_from_slice(b"RIFF");
+        assert!(read_header(&mut bytes.as_slice(), PYRAMID_VERSION, 7).is_err());
+        assert!(read_header(&mut &bytes[..10], PYRAMID_VERSION, 7).is_err());
+    }
+}
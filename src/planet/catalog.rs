@@ -0,0 +1,339 @@
+use super::{
+    atmosphere::Atmosphere,
+    datum::Datum,
+    detail::ProceduralDetail,
+    height_map::{HeightMap, HEIGHT_SCALLING},
+    Planet,
+};
+use crate::{
+    orbit::{HeliocentricOrbit, Orbit, OrbitPath, Rotation},
+    player::{Player, PlayerPositions},
+};
+use anyhow::bail;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+use std::path::Path;
+
+const DEFAULT_CATALOG: &str = "bodies.ron";
+
+/// Bodies to explore, described in a RON file, one at a time with its moons.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "b3e2c8a1-5d47-4f0e-a6c9-2e81f7d4b5a3"]
+pub struct BodyCatalog {
+    pub bodies: Vec<BodyDescription>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BodyDescription {
+    pub name: String,
+    pub shape: ShapeDescription,
+    /// Surface the heights of the height map are measured from, the sphere of
+    /// its radius without one.
+    #[serde(default)]
+    pub datum: Option<DatumDescription>,
+    /// Equirectangular albedo map used by the real color mode, starting at 0°E
+    /// like the height map.
+    #[serde(default)]
+    pub albedo: Option<String>,
+    /// Color of the real color mode without an albedo map.
+    #[serde(default)]
+    pub color: Option<Color>,
+    /// Vertical exaggeration of the relief.
+    #[serde(default = "default_height_scale")]
+    pub height_scale: f32,
+    /// Subdivisions of the icosahedron the mesh is built from.
+    #[serde(default)]
+    pub lod_depth: Option<u32>,
+    #[serde(default)]
+    pub detail: ProceduralDetail,
+    #[serde(default)]
+    pub atmosphere: Option<Atmosphere>,
+    /// Given to the body the scene is fixed to.
+    #[serde(default)]
+    pub rotation: Option<Rotation>,
+    /// Given to the moons of that body.
+    #[serde(default)]
+    pub orbit: Option<Orbit>,
+    /// Orbit around the sun of the body the scene is fixed to, that of the
+    /// Earth-Moon barycentre for the Moon.
+    #[serde(default)]
+    pub heliocentric_orbit: Option<Orbit>,
+    #[serde(default)]
+    pub moons: Vec<BodyDescription>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum ShapeDescription {
+    /// Heights above a sphere, read from a height map, all in metres.
+    HeightMap {
+        path: String,
+        format: HeightMapFormat,
+        radius: f32,
+        min_altitude: f32,
+        max_altitude: f32,
+        /// Height maps replacing the global one where they have samples, like
+        /// polar tiles or regional DTMs.
+        #[serde(default)]
+        tiles: Vec<HeightMapFile>,
+    },
+    /// Triaxial ellipsoid, with its radii in metres along the body-fixed axes.
+    Ellipsoid(Vec3),
+}
+
+/// Ellipsoid of revolution, with its radii in metres, and the height map of
+/// the geoid above it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatumDescription {
+    pub equatorial_radius: f32,
+    pub polar_radius: f32,
+    #[serde(default)]
+    pub geoid: Option<HeightMapFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeightMapFile {
+    pub path: String,
+    pub format: HeightMapFormat,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum HeightMapFormat {
+    /// MOLA MEGDR topography: big-endian 16-bit samples on a grid twice as
+    /// wide as high, starting at 90°N and 0°E.
+    Megdr,
+    /// Products described by a PDS3 label, like the LOLA LDEM of the Moon
+    /// and their polar stereographic tiles, or images starting with their
+    /// label, like the HiRISE DTMs.
+    Pds3,
+    /// Single band GeoTIFF, like the HRSC, CTX and HiRISE DTMs.
+    GeoTiff,
+}
+
+pub struct Bodies {
+    catalog: Handle<BodyCatalog>,
+    /// Index of the body explored in the catalog.
+    selected: usize,
+}
+
+fn default_height_scale() -> f32 {
+    HEIGHT_SCALLING
+}
+
+impl HeightMapFormat {
+    /// Extensions of the files the asset loader of the format reads.
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Self::Megdr => &["img"],
+            Self::Pds3 => &["lbl", "img"],
+            Self::GeoTiff => &["tif", "tiff"],
+        }
+    }
+
+    fn check(&self, path: &str) -> anyhow::Result<()> {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        let matches = |extension: &str| {
+            self.extensions()
+                .iter()
+                .any(|expected| extension.eq_ignore_ascii_case(expected))
+        };
+        if !extension.is_some_and(matches) {
+            bail!(
+                "Height map {} is not a .{} file, as the {:?} format requires",
+                path,
+                self.extensions().join(" or ."),
+                self
+            );
+        }
+        Ok(())
+    }
+}
+
+impl HeightMapFile {
+    fn load(&self, asset_server: &AssetServer) -> anyhow::Result<Handle<HeightMap>> {
+        self.format.check(&self.path)?;
+        Ok(asset_server.load(self.path.as_str()))
+    }
+}
+
+impl BodyDescription {
+    fn planet(&self, asset_server: &AssetServer) -> anyhow::Result<Planet> {
+        let planet = match &self.shape {
+            ShapeDescription::HeightMap {
+                path,
+                format,
+                radius,
+                min_altitude,
+                max_altitude,
+                tiles,
+            } => {
+                format.check(path)?;
+                let mut planet = Planet::new(
+                    *radius,
+                    *min_altitude,
+                    *max_altitude,
+                    asset_server.load(path.as_str()),
+                );
+                for tile in tiles {
+                    planet = planet.with_height_map_tile(tile.load(asset_server)?);
+                }
+                planet
+            }
+            ShapeDescription::Ellipsoid(radii) => Planet::ellipsoid(*radii),
+        };
+
+        let mut planet = planet
+            .with_height_scale(self.height_scale)
+            .with_procedural_detail(self.detail.clone());
+        if let Some(lod_depth) = self.lod_depth {
+            planet = planet.with_lod_depth(lod_depth);
+        }
+        if let Some(datum) = &self.datum {
+            planet = planet.with_datum(datum.datum(asset_server)?);
+        }
+        if let Some(color) = self.color {
+            planet = planet.with_color(color);
+        }
+        if let Some(albedo) = &self.albedo {
+            planet = planet.with_albedo(asset_server.load(albedo.as_str()));
+        }
+        if let Some(atmosphere) = &self.atmosphere {
+            planet = planet.with_atmosphere(atmosphere.clone());
+        }
+        Ok(planet)
+    }
+
+    /// Spawns the body and its moons.
+    fn spawn(&self, commands: &mut Commands, asset_server: &AssetServer) {
+        let planet = match self.planet(asset_server) {
+            Ok(planet) => planet,
+            Err(err) => {
+                error!("Invalid body {}: {:?}", self.name, err);
+                return;
+            }
+        };
+        let mut body = commands.spawn_bundle(TransformBundle::default());
+        body.insert(planet).insert(Name::new(self.name.clone()));
+        if let Some(rotation) = &self.rotation {
+            body.insert(rotation.clone());
+        }
+        if let Some(orbit) = &self.heliocentric_orbit {
+            body.insert(HeliocentricOrbit(orbit.clone()));
+        }
+        if let Some(orbit) = &self.orbit {
+            body.insert(orbit.clone());
+        }
+        for moon in &self.moons {
+            moon.spawn(commands, asset_server);
+        }
+    }
+}
+
+impl DatumDescription {
+    fn datum(&self, asset_server: &AssetServer) -> anyhow::Result<Datum> {
+        let datum = Datum::new(self.equatorial_radius, self.polar_radius);
+        Ok(match &self.geoid {
+            Some(geoid) => datum.with_geoid(geoid.load(asset_server)?),
+            None => datum,
+        })
+    }
+}
+
+/// Loads the catalog following `--catalog` on the command line, or the default one.
+pub fn setup_bodies(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let path = std::env::args()
+        .skip_while(|arg| arg != "--catalog")
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_CATALOG.to_string());
+    commands.insert_resource(Bodies {
+        catalog: asset_server.load(path.as_str()),
+        selected: 0,
+    });
+}
+
+pub fn spawn_bodies(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut events: EventReader<AssetEvent<BodyCatalog>>,
+    bodies: Res<Bodies>,
+    catalogs: Res<Assets<BodyCatalog>>,
+) {
+    for event in events.iter() {
+        let catalog = match event {
+            AssetEvent::Created { handle } if *handle == bodies.catalog => catalogs.get(handle),
+            _ => None,
+        };
+        let description = catalog.and_then(|catalog| catalog.bodies.get(bodies.selected));
+        if let Some(description) = description {
+            description.spawn(&mut commands, &asset_server);
+        }
+    }
+}
+
+/// Replaces the explored body and its moons by the next ones in the catalog
+/// when B is pressed, remembering where the player left them.
+#[allow(clippy::too_many_arguments)]
+pub fn body_selection_control(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    mut bodies: ResMut<Bodies>,
+    catalogs: Res<Assets<BodyCatalog>>,
+    mut positions: ResMut<PlayerPositions>,
+    planets: Query<(Entity, &Name), With<Planet>>,
+    paths: Query<Entity, With<OrbitPath>>,
+    player: Query<(Entity, &Transform, &Parent), With<Player>>,
+) {
+    if !keyboard.just_pressed(KeyCode::B) {
+        return;
+    }
+    let catalog = match catalogs.get(&bodies.catalog) {
+        Some(catalog) if catalog.bodies.len() > 1 => catalog,
+        _ => return,
+    };
+
+    // The player is taken out of the focused body before it is despawned.
+    if let Ok((entity, transform, parent)) = player.get_single() {
+        if let Ok((_, name)) = planets.get(parent.0) {
+            positions.remember(name, *transform);
+        }
+        commands.entity(parent.0).remove_children(&[entity]);
+    }
+    for (entity, _) in planets.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for entity in paths.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    bodies.selected = (bodies.selected + 1) % catalog.bodies.len();
+    let description = &catalog.bodies[bodies.selected];
+    info!("Exploring {}", description.name);
+    description.spawn(&mut commands, &asset_server);
+}
+
+#[derive(Default)]
+pub struct BodyCatalogLoader;
+
+impl AssetLoader for BodyCatalogLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let catalog: BodyCatalog = ron::de::from_bytes(bytes)?;
+            info!("Loaded {} bodies", catalog.bodies.len());
+            load_context.set_default_asset(LoadedAsset::new(catalog));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
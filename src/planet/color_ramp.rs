@@ -0,0 +1,225 @@
+use anyhow::{anyhow, bail};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_resource::{
+            AddressMode, Extent3d, FilterMode, SamplerDescriptor, TextureDimension, TextureFormat,
+        },
+        texture::Image,
+    },
+    utils::BoxedFuture,
+};
+
+const TEXTURE_WIDTH: usize = 256;
+pub const DEFAULT_COLOR_RAMP: &str = "color_ramps/mola_classic.cpt";
+/// Gradient shown until a ramp of `assets/color_ramps` has loaded, or if none
+/// can be.
+const FALLBACK_STOPS: [(f32, [u8; 3]); 5] = [
+    (0.0, [40, 40, 160]),
+    (0.25, [60, 160, 200]),
+    (0.5, [90, 170, 80]),
+    (0.75, [200, 140, 70]),
+    (1.0, [250, 250, 250]),
+];
+
+/// Color ramp loaded from a GMT `.cpt` file, uploaded to the GPU as a 1D texture.
+#[derive(Debug, TypeUuid)]
+#[uuid = "0b4d6c6a-3f57-4d43-9a3e-5c1f0e2b8d71"]
+pub struct ColorRamp {
+    stops: Vec<(f32, Color)>,
+    texture: Handle<Image>,
+}
+
+/// Color ramps available for the altitude color mode, cycled at runtime.
+pub struct ColorRamps {
+    ramps: Vec<Handle<ColorRamp>>,
+    active: usize,
+    fallback: ColorRamp,
+}
+
+impl ColorRamp {
+    pub fn texture(&self) -> Handle<Image> {
+        self.texture.clone()
+    }
+
+    pub fn sample(&self, t: f32) -> Color {
+        sample_stops(&self.stops, t)
+    }
+
+    fn build_texture(stops: &[(f32, Color)]) -> Image {
+        let data = (0..TEXTURE_WIDTH)
+            .flat_map(|i| {
+                let color = sample_stops(stops, i as f32 / (TEXTURE_WIDTH - 1) as f32);
+                color
+                    .as_rgba_f32()
+                    .map(|channel| (channel * 255.0).round() as u8)
+            })
+            .collect();
+
+        let mut image = Image::new(
+            Extent3d {
+                width: TEXTURE_WIDTH as u32,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D1,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        image.sampler_descriptor = SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..default()
+        };
+        image
+    }
+}
+
+fn sample_stops(stops: &[(f32, Color)], t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let next = stops
+        .iter()
+        .position(|(position, _)| *position >= t)
+        .unwrap_or(stops.len() - 1);
+    if next == 0 {
+        return stops[0].1;
+    }
+
+    let (p0, c0) = stops[next - 1];
+    let (p1, c1) = stops[next];
+    let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 0.0 };
+    let c0 = Vec4::from(c0.as_rgba_f32());
+    let c1 = Vec4::from(c1.as_rgba_f32());
+    Color::from(c0.lerp(c1, f))
+}
+
+fn parse_cpt(source: &str) -> Result<Vec<(f32, Color)>, anyhow::Error> {
+    let mut stops: Vec<(f32, Color)> = vec![];
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        // Background, foreground and NaN colors do not apply to a ramp.
+        if line.is_empty() || line.starts_with(['B', 'F', 'N']) {
+            continue;
+        }
+
+        let fields: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields.len() < 8 {
+            bail!("line {}: expected 'z0 r g b z1 r g b'", number + 1);
+        }
+
+        let values = fields[..8]
+            .iter()
+            .map(|field| field.parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|err| anyhow!("line {}: {}", number + 1, err))?;
+
+        for stop in values.chunks(4) {
+            let color = Color::rgb_u8(stop[1] as u8, stop[2] as u8, stop[3] as u8);
+            if stops.last() != Some(&(stop[0], color)) {
+                stops.push((stop[0], color));
+            }
+        }
+    }
+
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) if last.0 > first.0 => (first.0, last.0),
+        _ => bail!("color ramp needs at least two distinct stops"),
+    };
+    for stop in stops.iter_mut() {
+        stop.0 = (stop.0 - first) / (last - first);
+    }
+
+    Ok(stops)
+}
+
+impl ColorRamps {
+    pub fn new(asset_server: &AssetServer, images: &mut Assets<Image>) -> Self {
+        let mut ramps: Vec<(String, Handle<ColorRamp>)> = asset_server
+            .load_folder("color_ramps")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|handle| {
+                let path = asset_server.get_handle_path(&handle)?;
+                let path = path.path().to_string_lossy().replace('\\', "/");
+                Some((path, handle.typed()))
+            })
+            .collect();
+        ramps.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let active = ramps
+            .iter()
+            .position(|(path, _)| path == DEFAULT_COLOR_RAMP)
+            .unwrap_or(0);
+        info!("Found {} color ramps", ramps.len());
+
+        let stops: Vec<_> = FALLBACK_STOPS
+            .iter()
+            .map(|&(position, [r, g, b])| (position, Color::rgb_u8(r, g, b)))
+            .collect();
+        let texture = images.add(ColorRamp::build_texture(&stops));
+
+        Self {
+            ramps: ramps.into_iter().map(|(_, handle)| handle).collect(),
+            active,
+            fallback: ColorRamp { stops, texture },
+        }
+    }
+
+    pub fn active(&self) -> Option<&Handle<ColorRamp>> {
+        self.ramps.get(self.active)
+    }
+
+    /// The active ramp, or the built-in gradient while it is not loaded.
+    pub fn active_ramp<'a>(&'a self, color_ramps: &'a Assets<ColorRamp>) -> &'a ColorRamp {
+        self.active()
+            .and_then(|handle| color_ramps.get(handle))
+            .unwrap_or(&self.fallback)
+    }
+
+    pub fn active_texture(&self, color_ramps: &Assets<ColorRamp>) -> Handle<Image> {
+        self.active_ramp(color_ramps).texture()
+    }
+
+    pub fn cycle(&mut self) {
+        if !self.ramps.is_empty() {
+            self.active = (self.active + 1) % self.ramps.len();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ColorRampAssetLoader;
+
+impl AssetLoader for ColorRampAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let stops = parse_cpt(std::str::from_utf8(bytes)?)?;
+            let texture = load_context.set_labeled_asset(
+                "texture",
+                LoadedAsset::new(ColorRamp::build_texture(&stops)),
+            );
+            info!(
+                "Loaded color ramp {:?} with {} stops",
+                load_context.path(),
+                stops.len()
+            );
+            load_context.set_default_asset(LoadedAsset::new(ColorRamp { stops, texture }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["cpt"]
+    }
+}
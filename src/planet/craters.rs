@@ -0,0 +1,242 @@
+use super::detail::lattice_hash;
+use bevy::{math::const_vec3, prelude::*};
+use serde::Deserialize;
+
+/// Depth of a fresh simple crater relative to its diameter.
+const DEPTH_RATIO: f32 = 0.2;
+/// Height of a fresh crater rim relative to its diameter.
+const RIM_RATIO: f32 = 0.04;
+/// Distance, in crater radii, at which the ejecta blanket vanishes.
+const EJECTA_EXTENT: f32 = 2.5;
+const BOULDER_MIN_DIAMETER: f32 = 1.0;
+const BOULDER_MAX_DIAMETER: f32 = 4.0;
+/// Height of a boulder relative to its diameter, the rest is buried.
+const BOULDER_HEIGHT_RATIO: f32 = 0.4;
+/// Axis and tangent axes of each face of the cube enclosing the sphere.
+const CUBE_FACES: [(Vec3, Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y, Vec3::Z),
+    (const_vec3!([-1.0, 0.0, 0.0]), Vec3::Y, Vec3::Z),
+    (Vec3::Y, Vec3::Z, Vec3::X),
+    (const_vec3!([0.0, -1.0, 0.0]), Vec3::Z, Vec3::X),
+    (Vec3::Z, Vec3::X, Vec3::Y),
+    (const_vec3!([0.0, 0.0, -1.0]), Vec3::X, Vec3::Y),
+];
+
+/// Small craters and boulders scattered over a planet, following a power law
+/// size-frequency distribution.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CraterField {
+    /// Craters wider than `min_diameter` per square kilometre.
+    pub density: f32,
+    /// Exponent of the cumulative size-frequency distribution.
+    pub power_law: f32,
+    /// Diameter range of the generated craters, in metres.
+    pub min_diameter: f32,
+    pub max_diameter: f32,
+    /// Boulders per square kilometre.
+    pub boulder_density: f32,
+}
+
+impl Default for CraterField {
+    fn default() -> Self {
+        Self {
+            density: 0.02,
+            power_law: 2.0,
+            min_diameter: 500.0,
+            max_diameter: 8000.0,
+            boulder_density: 2000.0,
+        }
+    }
+}
+
+/// Something scattered over the surface and shaped by its distance to its center.
+struct Feature {
+    direction: Vec3,
+    diameter: f32,
+    /// Between 0 for a feature worn flat and 1 for a fresh one.
+    freshness: f32,
+}
+
+impl CraterField {
+    /// Height, in metres, added by the craters and boulders at a position,
+    /// leaving out the ones smaller than the `footprint`, in radians.
+    pub fn height_at(
+        &self,
+        normalized_position: Vec3,
+        radius: f32,
+        footprint: f32,
+        seed: u32,
+    ) -> f32 {
+        let mut height = 0.0;
+        let footprint = footprint * radius;
+
+        // Each octave of diameters is generated on its own grid of patches.
+        let mut level = 0;
+        let mut min_diameter = self.min_diameter;
+        while min_diameter < self.max_diameter {
+            let max_diameter = (min_diameter * 2.0).min(self.max_diameter);
+            if max_diameter < footprint {
+                min_diameter = max_diameter;
+                level += 1;
+                continue;
+            }
+            let density =
+                self.cumulative_density(min_diameter) - self.cumulative_density(max_diameter);
+            let reach = EJECTA_EXTENT * max_diameter / 2.0;
+            for_each_feature(
+                normalized_position,
+                radius,
+                reach,
+                density,
+                seed.wrapping_add(level),
+                |rng| self.sample_diameter(rng, min_diameter, max_diameter),
+                |crater, distance| height += crater_profile(crater, distance),
+            );
+            min_diameter = max_diameter;
+            level += 1;
+        }
+
+        if BOULDER_MAX_DIAMETER < footprint {
+            return height;
+        }
+        let boulders = BOULDER_MIN_DIAMETER..BOULDER_MAX_DIAMETER;
+        for_each_feature(
+            normalized_position,
+            radius,
+            BOULDER_MAX_DIAMETER / 2.0,
+            self.boulder_density / 1e6,
+            seed.wrapping_add(level),
+            |rng| boulders.start + rng.next() * (boulders.end - boulders.start),
+            |boulder, distance| height += boulder_profile(boulder, distance),
+        );
+
+        height
+    }
+
+    /// Craters wider than `diameter` per square metre.
+    fn cumulative_density(&self, diameter: f32) -> f32 {
+        self.density / 1e6 * (self.min_diameter / diameter).powf(self.power_law)
+    }
+
+    /// Inverts the power law restricted to a diameter range.
+    fn sample_diameter(&self, rng: &mut Rng, min_diameter: f32, max_diameter: f32) -> f32 {
+        let low = min_diameter.powf(-self.power_law);
+        let high = max_diameter.powf(-self.power_law);
+        (low - rng.next() * (low - high)).powf(-1.0 / self.power_law)
+    }
+}
+
+/// Visits the features reaching a position, `density` being their number per
+/// square metre.
+///
+/// Each face of the cube enclosing the sphere is cut into square patches, each
+/// one seeding its own features, and the patches of all the faces the position
+/// is close to are searched so the features cross the face edges.
+fn for_each_feature(
+    normalized_position: Vec3,
+    radius: f32,
+    reach: f32,
+    density: f32,
+    seed: u32,
+    mut diameter: impl FnMut(&mut Rng) -> f32,
+    mut visit: impl FnMut(&Feature, f32),
+) {
+    if density <= 0.0 {
+        return;
+    }
+
+    let angular_reach = reach / radius;
+    let patches = (1.0 / angular_reach).ceil();
+    let patch_size = 2.0 / patches;
+
+    for (face, (axis, first, second)) in CUBE_FACES.iter().enumerate() {
+        let cosine = normalized_position.dot(*axis);
+        if cosine <= 0.0 {
+            continue;
+        }
+        // Gnomonic projection onto the face, which stretches distances by up
+        // to 1 + r² away from its center.
+        let face_position = Vec2::new(
+            normalized_position.dot(*first),
+            normalized_position.dot(*second),
+        ) / cosine;
+        // Nothing on the face can reach a position projected too far from it,
+        // even stretched by 3 at its corners.
+        if face_position.abs().max_element() > 1.0 + 4.0 * angular_reach {
+            continue;
+        }
+        let face_reach = 1.1 * angular_reach * (1.0 + face_position.length_squared());
+        let min = ((face_position - face_reach + 1.0) / patch_size)
+            .floor()
+            .max(Vec2::ZERO);
+        let max = ((face_position + face_reach + 1.0) / patch_size)
+            .floor()
+            .min(Vec2::splat(patches - 1.0));
+
+        for row in min.y as i32..=max.y as i32 {
+            for col in min.x as i32..=max.x as i32 {
+                let corner = Vec2::new(col as f32, row as f32) * patch_size - 1.0;
+                let center = corner + patch_size / 2.0;
+                // Patches far from the face center cover less of the sphere.
+                let area =
+                    (patch_size * radius).powi(2) / (1.0 + center.length_squared()).powf(1.5);
+                let count = density * area;
+
+                let mut rng = Rng::new(lattice_hash(col, row, face as i32, seed));
+                let patch_count = count.floor() as u32 + (rng.next() < count.fract()) as u32;
+                for _ in 0..patch_count {
+                    let position = corner + Vec2::new(rng.next(), rng.next()) * patch_size;
+                    let feature = Feature {
+                        direction: (*axis + *first * position.x + *second * position.y).normalize(),
+                        diameter: diameter(&mut rng),
+                        freshness: rng.next(),
+                    };
+
+                    let distance = normalized_position.distance(feature.direction) * radius;
+                    if distance < reach {
+                        visit(&feature, distance);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parabolic bowl surrounded by a raised rim and an ejecta blanket.
+fn crater_profile(crater: &Feature, distance: f32) -> f32 {
+    let r = 2.0 * distance / crater.diameter;
+    let depth = DEPTH_RATIO * crater.diameter;
+    let rim = RIM_RATIO * crater.diameter;
+    let height = if r < 1.0 {
+        -depth + (depth + rim) * r * r
+    } else {
+        let edge = EJECTA_EXTENT.powi(-3);
+        rim * (r.powi(-3) - edge).max(0.0) / (1.0 - edge)
+    };
+    // Older craters are partly filled and their rims eroded.
+    height * (0.2 + 0.8 * crater.freshness)
+}
+
+fn boulder_profile(boulder: &Feature, distance: f32) -> f32 {
+    let r = 2.0 * distance / boulder.diameter;
+    let height = BOULDER_HEIGHT_RATIO * boulder.diameter * (0.5 + 0.5 * boulder.freshness);
+    height * (1.0 - r * r).max(0.0)
+}
+
+/// Small generator drawing the features of a patch from its hash.
+struct Rng(u32);
+
+impl Rng {
+    fn new(seed: u32) -> Self {
+        Self(seed | 1)
+    }
+
+    /// Uniform value in [0, 1).
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
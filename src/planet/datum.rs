@@ -0,0 +1,95 @@
+use super::height_map::{HeightMap, Relief};
+use bevy::prelude::*;
+
+/// Surface the heights of a planet are measured from: an ellipsoid of
+/// revolution around its north pole, optionally raised by the heights of a
+/// geoid grid, such as the areoid of Mars.
+#[derive(Debug, Clone)]
+pub struct Datum {
+    /// In metres.
+    equatorial_radius: f32,
+    polar_radius: f32,
+    /// Heights of the geoid above the ellipsoid.
+    geoid: Option<Handle<HeightMap>>,
+}
+
+/// Relief placed on the datum of a planet, its heights taken above the sphere
+/// of the planet radius.
+pub struct DatumRelief<'a> {
+    relief: &'a dyn Relief,
+    radius: f32,
+    datum: Option<&'a Datum>,
+    geoid: Option<&'a HeightMap>,
+}
+
+impl Datum {
+    pub fn new(equatorial_radius: f32, polar_radius: f32) -> Self {
+        Self {
+            equatorial_radius,
+            polar_radius,
+            geoid: None,
+        }
+    }
+
+    pub fn with_geoid(mut self, geoid: Handle<HeightMap>) -> Self {
+        self.geoid = Some(geoid);
+        self
+    }
+
+    pub fn geoid(&self) -> Option<&Handle<HeightMap>> {
+        self.geoid.as_ref()
+    }
+
+    pub fn max_radius(&self) -> f32 {
+        self.equatorial_radius.max(self.polar_radius)
+    }
+
+    /// Distance from the center to the ellipsoid in a direction.
+    pub fn ellipsoid_radius_at(&self, normalized_position: Vec3) -> f32 {
+        let horizontal = normalized_position.x.hypot(normalized_position.z);
+        self.equatorial_radius * self.polar_radius
+            / (self.polar_radius * horizontal).hypot(self.equatorial_radius * normalized_position.y)
+    }
+}
+
+impl<'a> DatumRelief<'a> {
+    /// Relief on the sphere of the planet radius, until a datum is given.
+    pub fn new(relief: &'a dyn Relief, radius: f32) -> Self {
+        Self {
+            relief,
+            radius,
+            datum: None,
+            geoid: None,
+        }
+    }
+
+    pub fn with_datum(mut self, datum: &'a Datum, geoid: Option<&'a HeightMap>) -> Self {
+        self.datum = Some(datum);
+        self.geoid = geoid;
+        self
+    }
+
+    /// Height of the datum above the sphere.
+    pub fn datum_height_at(&self, normalized_position: Vec3) -> f32 {
+        let datum = match self.datum {
+            Some(datum) => datum,
+            None => return 0.0,
+        };
+        let geoid_height = self
+            .geoid
+            .and_then(|geoid| geoid.sample(normalized_position, 0.0))
+            .unwrap_or(0.0);
+        datum.ellipsoid_radius_at(normalized_position) - self.radius + geoid_height
+    }
+}
+
+impl<'a> Relief for DatumRelief<'a> {
+    fn get_height_at(&self, normalized_position: Vec3, footprint: f32) -> f32 {
+        self.datum_height_at(normalized_position)
+            + self.relief.get_height_at(normalized_position, footprint)
+    }
+
+    fn resolution(&self) -> f32 {
+        self.relief.resolution()
+    }
+}
@@ -0,0 +1,127 @@
+use super::{view::ColorMode, Planet};
+use bevy::{
+    pbr::wireframe::Wireframe,
+    prelude::*,
+    render::{render_resource::WgpuFeatures, renderer::RenderDevice},
+};
+
+const FONT_SIZE: f32 = 14.0;
+
+/// Views of the tessellation of the planets, toggled at runtime.
+pub struct MeshDebug {
+    wireframe: bool,
+    /// Whether the graphics adapter draws lines, which the wireframe needs.
+    wireframe_supported: bool,
+}
+
+#[derive(Component)]
+pub struct MeshStatisticsLabel;
+
+pub fn setup_mesh_debug(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    render_device: Res<RenderDevice>,
+) {
+    let wireframe_supported = render_device
+        .features()
+        .contains(WgpuFeatures::POLYGON_MODE_LINE);
+    if !wireframe_supported {
+        warn!("The graphics adapter cannot draw lines, the wireframe view is disabled");
+    }
+    commands.insert_resource(MeshDebug {
+        wireframe: false,
+        wireframe_supported,
+    });
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: FONT_SIZE,
+                    color: Color::WHITE,
+                },
+                default(),
+            ),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(MeshStatisticsLabel);
+}
+
+pub fn wireframe_control(keyboard: Res<Input<KeyCode>>, mut debug: ResMut<MeshDebug>) {
+    if keyboard.just_pressed(KeyCode::F5) {
+        if debug.wireframe_supported {
+            debug.wireframe = !debug.wireframe;
+        } else {
+            warn!("The wireframe view is not supported by the graphics adapter");
+        }
+    }
+}
+
+pub fn update_wireframe(
+    mut commands: Commands,
+    debug: Res<MeshDebug>,
+    planets: Query<Entity, With<Planet>>,
+    added: Query<Entity, Added<Planet>>,
+) {
+    let planets = if debug.is_changed() {
+        planets.iter().collect::<Vec<_>>()
+    } else {
+        added.iter().collect()
+    };
+    for entity in planets {
+        if debug.wireframe {
+            commands.entity(entity).insert(Wireframe);
+        } else {
+            commands.entity(entity).remove::<Wireframe>();
+        }
+    }
+}
+
+/// Counts the vertices and triangles of the planet meshes drawn in the last
+/// frame, shown with the wireframe and in the subdivision color mode.
+pub fn update_mesh_statistics(
+    debug: Res<MeshDebug>,
+    color_mode: Res<ColorMode>,
+    meshes: Res<Assets<Mesh>>,
+    planets: Query<(&Handle<Mesh>, &ComputedVisibility), With<Planet>>,
+    mut labels: Query<(&mut Text, &mut Visibility), With<MeshStatisticsLabel>>,
+) {
+    let is_visible = debug.wireframe || *color_mode == ColorMode::Subdivision;
+    let mut drawn = 0;
+    let mut vertices = 0;
+    let mut triangles = 0;
+    if is_visible {
+        let visible_meshes = planets
+            .iter()
+            .filter(|(_, visibility)| visibility.is_visible)
+            .filter_map(|(handle, _)| meshes.get(handle));
+        for mesh in visible_meshes {
+            drawn += 1;
+            vertices += mesh.count_vertices();
+            triangles += mesh.indices().map_or(0, |indices| indices.len()) / 3;
+        }
+    }
+
+    for (mut text, mut visibility) in labels.iter_mut() {
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+        if is_visible {
+            text.sections[0].value = format!(
+                "{} planet meshes drawn, {} vertices, {} triangles",
+                drawn, vertices, triangles
+            );
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use super::{craters::CraterField, height_map::Relief, LoadingPlanet, Planet};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+const LACUNARITY: f32 = 2.0;
+const PERSISTENCE: f32 = 0.5;
+/// Share of the amplitude kept on perfectly flat terrain.
+const FLAT_ROUGHNESS: f32 = 0.25;
+/// Slope, as rise over run, above which the full amplitude is used.
+const ROUGH_SLOPE: f32 = 0.2;
+
+/// Fractal noise added to the height map to synthesize details below its resolution.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProceduralDetail {
+    pub enabled: bool,
+    pub seed: u32,
+    pub octaves: u32,
+    /// Amplitude, in metres, of the first octave on rough terrain.
+    pub amplitude: f32,
+    pub craters: CraterField,
+}
+
+impl Default for ProceduralDetail {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            seed: 0,
+            octaves: 4,
+            amplitude: 40.0,
+            craters: CraterField::default(),
+        }
+    }
+}
+
+/// Relief of a planet as drawn, with procedural detail layered on top of it and
+/// its heights exaggerated by `height_scale`.
+pub struct DetailedRelief<'a> {
+    base: &'a dyn Relief,
+    detail: &'a ProceduralDetail,
+    radius: f32,
+    height_scale: f32,
+}
+
+impl<'a> DetailedRelief<'a> {
+    pub fn new(
+        base: &'a dyn Relief,
+        detail: &'a ProceduralDetail,
+        radius: f32,
+        height_scale: f32,
+    ) -> Self {
+        Self {
+            base,
+            detail,
+            radius,
+            height_scale,
+        }
+    }
+
+    /// Slope of the base relief around a position, used as its roughness.
+    fn slope_at(&self, normalized_position: Vec3, footprint: f32) -> f32 {
+        let step = self.base.resolution().max(footprint);
+        let (east, north) = tangent_axes(normalized_position);
+        let height_at = |offset: Vec3| {
+            let position = (normalized_position + offset * step).normalize();
+            self.base.get_height_at(position, footprint)
+        };
+
+        let east_slope = height_at(east) - height_at(-east);
+        let north_slope = height_at(north) - height_at(-north);
+        let run = 2.0 * step * self.radius;
+        (east_slope.powi(2) + north_slope.powi(2)).sqrt() / run
+    }
+
+    /// Noise of the octaves resolved over the `footprint`, faded out as their
+    /// wavelength shrinks from two footprints to one, dropped below it.
+    fn noise_at(&self, normalized_position: Vec3, footprint: f32) -> f32 {
+        // The first octave has the wavelength of a base relief sample.
+        let mut frequency = 1.0 / self.base.resolution();
+        let mut amplitude = 1.0;
+        let mut noise = 0.0;
+        for octave in 0..self.detail.octaves {
+            let fade = (1.0 / (frequency * footprint) - 1.0).min(1.0);
+            if fade <= 0.0 {
+                break;
+            }
+            let seed = self.detail.seed.wrapping_add(octave);
+            noise += fade * amplitude * value_noise(normalized_position * frequency, seed);
+            frequency *= LACUNARITY;
+            amplitude *= PERSISTENCE;
+        }
+        noise
+    }
+}
+
+impl<'a> Relief for DetailedRelief<'a> {
+    fn get_height_at(&self, normalized_position: Vec3, footprint: f32) -> f32 {
+        let height = self.base.get_height_at(normalized_position, footprint);
+        if !self.detail.enabled {
+            return self.height_scale * height;
+        }
+
+        let roughness = FLAT_ROUGHNESS
+            + (1.0 - FLAT_ROUGHNESS)
+                * (self.slope_at(normalized_position, footprint) / ROUGH_SLOPE).min(1.0);
+        let detail =
+            self.detail.amplitude * roughness * self.noise_at(normalized_position, footprint)
+                + self.detail.craters.height_at(
+                    normalized_position,
+                    self.radius,
+                    footprint,
+                    self.detail.seed,
+                );
+        self.height_scale * (height + detail)
+    }
+
+    fn resolution(&self) -> f32 {
+        self.base.resolution()
+    }
+}
+
+pub fn procedural_detail_control(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    mut planets: Query<(Entity, &mut Planet), Without<LoadingPlanet>>,
+) {
+    if keyboard.just_pressed(KeyCode::P) {
+        for (entity, mut planet) in planets.iter_mut() {
+            planet.detail.enabled = !planet.detail.enabled;
+            info!(
+                "Procedural detail {}",
+                if planet.detail.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+            commands.entity(entity).insert(LoadingPlanet);
+        }
+    }
+}
+
+fn tangent_axes(normalized_position: Vec3) -> (Vec3, Vec3) {
+    let east = Vec3::Y.cross(normalized_position);
+    let east = if east.length_squared() > 0.0 {
+        east.normalize()
+    } else {
+        Vec3::X
+    };
+    (east, normalized_position.cross(east))
+}
+
+/// Smoothly interpolated lattice noise in [-1, 1], deterministic for a seed.
+fn value_noise(position: Vec3, seed: u32) -> f32 {
+    let cell = position.floor();
+    let local = position - cell;
+    let weight = local * local * (Vec3::splat(3.0) - 2.0 * local);
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+    let lattice = |dx: i32, dy: i32, dz: i32| lattice_value(x + dx, y + dy, z + dz, seed);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let x00 = lerp(lattice(0, 0, 0), lattice(1, 0, 0), weight.x);
+    let x10 = lerp(lattice(0, 1, 0), lattice(1, 1, 0), weight.x);
+    let x01 = lerp(lattice(0, 0, 1), lattice(1, 0, 1), weight.x);
+    let x11 = lerp(lattice(0, 1, 1), lattice(1, 1, 1), weight.x);
+    let y0 = lerp(x00, x10, weight.y);
+    let y1 = lerp(x01, x11, weight.y);
+    lerp(y0, y1, weight.z)
+}
+
+fn lattice_value(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    lattice_hash(x, y, z, seed) as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// Well mixed hash of a lattice point, the base of all the seeded randomness.
+pub fn lattice_hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut hash = seed.wrapping_mul(0x9e37_79b9).wrapping_add(0x27d4_eb2f);
+    for coordinate in [x, y, z] {
+        hash ^= coordinate as u32;
+        hash = hash.wrapping_mul(0x85eb_ca6b);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(0xc2b2_ae35);
+        hash ^= hash >> 16;
+    }
+    hash
+}
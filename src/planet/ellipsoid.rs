@@ -0,0 +1,53 @@
+use super::height_map::{body_axes, Relief};
+use bevy::prelude::*;
+use std::f32::consts::PI;
+
+/// Angular size of the finest detail expected over the ellipsoid, setting the
+/// scale of the procedural detail laid over it.
+const RESOLUTION: f32 = 2.0 * PI / 2048.0;
+
+/// Triaxial ellipsoid approximating the figure of a small irregular body, its
+/// heights taken above the sphere of the same volume.
+#[derive(Debug, Clone)]
+pub struct Ellipsoid {
+    /// Radii along the body-fixed axes, in metres.
+    radii: Vec3,
+    mean_radius: f32,
+}
+
+impl Ellipsoid {
+    pub fn new(radii: Vec3) -> Self {
+        Self {
+            radii,
+            mean_radius: (radii.x * radii.y * radii.z).cbrt(),
+        }
+    }
+
+    pub fn mean_radius(&self) -> f32 {
+        self.mean_radius
+    }
+
+    /// Lowest and highest heights of the surface.
+    pub fn height_range(&self) -> (f32, f32) {
+        (
+            self.radii.min_element() - self.mean_radius,
+            self.radii.max_element() - self.mean_radius,
+        )
+    }
+
+    /// Distance from the center to the surface along a direction.
+    fn radius_at(&self, normalized_position: Vec3) -> f32 {
+        let body_position = body_axes().transpose() * normalized_position;
+        1.0 / (body_position / self.radii).length()
+    }
+}
+
+impl Relief for Ellipsoid {
+    fn get_height_at(&self, normalized_position: Vec3, _footprint: f32) -> f32 {
+        self.radius_at(normalized_position) - self.mean_radius
+    }
+
+    fn resolution(&self) -> f32 {
+        RESOLUTION
+    }
+}
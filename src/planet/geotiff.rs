@@ -0,0 +1,330 @@
+use super::{
+    height_map::{HeightMap, Samples},
+    load_errors::HeightMapErrors,
+    projection::Projection,
+};
+use anyhow::{anyhow, bail, Context};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use std::io::Cursor;
+use tiff::{
+    decoder::{Decoder, DecodingResult, Limits},
+    tags::Tag,
+    ColorType,
+};
+
+const GEO_DOUBLE_PARAMS_TAG: u16 = 34736;
+
+const MODEL_TYPE: u16 = 1024;
+const RASTER_TYPE: u16 = 1025;
+const SEMI_MAJOR_AXIS: u16 = 2057;
+const COORDINATE_TRANSFORMATION: u16 = 3075;
+const STANDARD_PARALLEL: u16 = 3078;
+const ORIGIN_LONGITUDE: u16 = 3080;
+const ORIGIN_LATITUDE: u16 = 3081;
+const FALSE_EASTING: u16 = 3082;
+const FALSE_NORTHING: u16 = 3083;
+const CENTER_LONGITUDE: u16 = 3088;
+const CENTER_LATITUDE: u16 = 3089;
+const SCALE_AT_ORIGIN: u16 = 3092;
+const POLE_LONGITUDE: u16 = 3095;
+
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+const STEREOGRAPHIC: u16 = 14;
+const POLAR_STEREOGRAPHIC: u16 = 15;
+const EQUIRECTANGULAR: u16 = 17;
+
+/// Values of the GeoTIFF keys, short ones and those in the double parameters.
+struct GeoKeys {
+    shorts: HashMap<u16, u16>,
+    doubles: HashMap<u16, f64>,
+}
+
+/// Placement of the image in the model space: size of a pixel and model
+/// coordinates of the pixel position `column`, `row`.
+struct Georeference {
+    scale: (f64, f64),
+    column: f64,
+    row: f64,
+    x: f64,
+    y: f64,
+}
+
+impl GeoKeys {
+    fn read<R: std::io::Read + std::io::Seek>(decoder: &mut Decoder<R>) -> anyhow::Result<Self> {
+        let directory = decoder
+            .get_tag_u16_vec(Tag::GeoKeyDirectoryTag)
+            .map_err(|_| anyhow!("Missing GeoTIFF keys"))?;
+        let params = decoder
+            .get_tag_f64_vec(Tag::Unknown(GEO_DOUBLE_PARAMS_TAG))
+            .unwrap_or_default();
+
+        let mut keys = Self {
+            shorts: HashMap::default(),
+            doubles: HashMap::default(),
+        };
+        for entry in directory.get(4..).unwrap_or_default().chunks_exact(4) {
+            let (key, location, value) = (entry[0], entry[1], entry[3]);
+            match location {
+                0 => {
+                    keys.shorts.insert(key, value);
+                }
+                GEO_DOUBLE_PARAMS_TAG => {
+                    let value = params
+                        .get(value as usize)
+                        .ok_or_else(|| anyhow!("GeoTIFF key {} out of its parameters", key))?;
+                    keys.doubles.insert(key, *value);
+                }
+                _ => {}
+            }
+        }
+        Ok(keys)
+    }
+
+    fn short(&self, key: u16) -> Option<u16> {
+        self.shorts.get(&key).copied()
+    }
+
+    /// First of the given keys present among the double parameters.
+    fn double(&self, keys: &[u16]) -> Option<f64> {
+        keys.iter().find_map(|key| self.doubles.get(key).copied())
+    }
+}
+
+impl Georeference {
+    fn read<R: std::io::Read + std::io::Seek>(
+        decoder: &mut Decoder<R>,
+        keys: &GeoKeys,
+    ) -> anyhow::Result<Self> {
+        let scale = decoder
+            .get_tag_f64_vec(Tag::ModelPixelScaleTag)
+            .map_err(|_| anyhow!("Missing pixel scale, only north-up images are supported"))?;
+        let tie_point = decoder
+            .get_tag_f64_vec(Tag::ModelTiepointTag)
+            .map_err(|_| anyhow!("Missing tie point"))?;
+        if scale.len() < 2 || tie_point.len() < 6 {
+            bail!("Invalid pixel scale or tie point");
+        }
+        if (scale[0] - scale[1]).abs() > 1e-6 * scale[0] {
+            bail!("Only square pixels are supported");
+        }
+
+        // The tie point is on the corner of the pixel unless its value is the
+        // one of its center.
+        let half_pixel = if keys.short(RASTER_TYPE) == Some(RASTER_PIXEL_IS_POINT) {
+            0.0
+        } else {
+            0.5
+        };
+        Ok(Self {
+            scale: (scale[0], scale[1]),
+            column: tie_point[0] - half_pixel,
+            row: tie_point[1] - half_pixel,
+            x: tie_point[3],
+            y: tie_point[4],
+        })
+    }
+
+    fn projection(&self, keys: &GeoKeys) -> anyhow::Result<Projection> {
+        let (scale_x, scale_y) = self.scale;
+        match keys.short(MODEL_TYPE) {
+            Some(MODEL_TYPE_GEOGRAPHIC) => Ok(Projection::Equirectangular {
+                pixels_per_degree: (1.0 / scale_y) as f32,
+                line_offset: (self.row + self.y / scale_y) as f32,
+                sample_offset: self.column as f32,
+                center_longitude: self.x as f32,
+                standard_parallel: 0.0,
+            }),
+            Some(MODEL_TYPE_PROJECTED) => {
+                let radius = keys
+                    .double(&[SEMI_MAJOR_AXIS])
+                    .ok_or_else(|| anyhow!("Missing the radius of the body"))?;
+                let false_easting = keys.double(&[FALSE_EASTING]).unwrap_or(0.0);
+                let false_northing = keys.double(&[FALSE_NORTHING]).unwrap_or(0.0);
+                let sample_offset = self.column + (false_easting - self.x) / scale_x;
+                let line_offset = self.row + (self.y - false_northing) / scale_y;
+                let origin_latitude = keys
+                    .double(&[ORIGIN_LATITUDE, CENTER_LATITUDE])
+                    .unwrap_or(0.0);
+
+                match keys.short(COORDINATE_TRANSFORMATION) {
+                    Some(EQUIRECTANGULAR) => {
+                        let pixels_per_degree = radius.to_radians() / scale_y;
+                        Ok(Projection::Equirectangular {
+                            pixels_per_degree: pixels_per_degree as f32,
+                            line_offset: (line_offset + origin_latitude * pixels_per_degree)
+                                as f32,
+                            sample_offset: sample_offset as f32,
+                            center_longitude: keys
+                                .double(&[CENTER_LONGITUDE, ORIGIN_LONGITUDE])
+                                .unwrap_or(0.0) as f32,
+                            standard_parallel: keys.double(&[STANDARD_PARALLEL]).unwrap_or(0.0)
+                                as f32,
+                        })
+                    }
+                    Some(POLAR_STEREOGRAPHIC) | Some(STEREOGRAPHIC)
+                        if origin_latitude.abs() == 90.0 =>
+                    {
+                        let scale_at_origin = keys.double(&[SCALE_AT_ORIGIN]).unwrap_or(1.0);
+                        Ok(Projection::PolarStereographic {
+                            north: origin_latitude > 0.0,
+                            scale: scale_x as f32,
+                            line_offset: line_offset as f32,
+                            sample_offset: sample_offset as f32,
+                            center_longitude: keys
+                                .double(&[POLE_LONGITUDE, ORIGIN_LONGITUDE])
+                                .unwrap_or(0.0) as f32,
+                            radius: (radius * scale_at_origin) as f32,
+                        })
+                    }
+                    transformation => bail!(
+                        "Unsupported projection {:?}, only equirectangular and polar stereographic ones are",
+                        transformation
+                    ),
+                }
+            }
+            model_type => bail!("Unsupported GeoTIFF model type {:?}", model_type),
+        }
+    }
+}
+
+/// Single band GeoTIFF height maps, like the HRSC, CTX and HiRISE DTMs, in
+/// geographic coordinates or projected on an equirectangular or polar
+/// stereographic grid, with 16-bit or 32-bit integer or 32-bit float samples.
+pub struct GeoTiffHeightMapLoader {
+    errors: HeightMapErrors,
+}
+
+impl FromWorld for GeoTiffHeightMapLoader {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            errors: HeightMapErrors::shared(world),
+        }
+    }
+}
+
+fn read_height_map(bytes: &[u8]) -> anyhow::Result<HeightMap> {
+    let mut decoder = Decoder::new(Cursor::new(bytes))
+        .context("Not a TIFF file")?
+        .with_limits(Limits::unlimited());
+    let (width, height) = decoder.dimensions()?;
+    match decoder.colortype()? {
+        ColorType::Gray(_) => {}
+        color_type => bail!(
+            "Only single band images are supported, not {:?}",
+            color_type
+        ),
+    }
+    let keys = GeoKeys::read(&mut decoder)?;
+    let projection = Georeference::read(&mut decoder, &keys)?.projection(&keys)?;
+
+    // GDAL writes the sample value of the holes as text.
+    let no_data = match decoder.get_tag_ascii_string(Tag::GdalNodata) {
+        Ok(text) => Some(
+            text.trim_matches(char::from(0))
+                .trim()
+                .parse::<f64>()
+                .with_context(|| format!("Invalid no-data value {}", text))? as f32,
+        ),
+        Err(_) => None,
+    };
+
+    let data = match decoder.read_image().context("Cannot read the samples")? {
+        DecodingResult::I16(samples) => Samples::I16(samples),
+        DecodingResult::U16(samples) => Samples::U16(samples),
+        DecodingResult::I32(samples) => Samples::I32(samples),
+        DecodingResult::F32(samples) => Samples::F32(samples),
+        _ => bail!("Only 16-bit and 32-bit integer or 32-bit float samples are supported"),
+    };
+    HeightMap::new(width as usize, height as usize, data, no_data, projection)
+}
+
+impl AssetLoader for GeoTiffHeightMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let height_map = self
+                .errors
+                .record(load_context.path(), read_height_map(bytes))?;
+            height_map.log_loaded(load_context.path());
+            load_context.set_default_asset(LoadedAsset::new(height_map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tif", "tiff"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planet::height_map::{geographic_direction, Relief};
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    const NO_DATA: f32 = -3.402_822_7e38;
+
+    /// Geographic GeoTIFF of 4 x 2 float samples of a degree, from 10°N 20°E.
+    fn geotiff(samples: &[f32], georeferenced: bool) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut bytes).unwrap();
+        let mut image = encoder.new_image::<colortype::Gray32Float>(4, 2).unwrap();
+        if georeferenced {
+            let tags = image.encoder();
+            let directory = [1, 1, 0, 1, MODEL_TYPE, 0, 1, MODEL_TYPE_GEOGRAPHIC];
+            tags.write_tag(Tag::GeoKeyDirectoryTag, &directory[..])
+                .unwrap();
+            tags.write_tag(Tag::ModelPixelScaleTag, &[1.0, 1.0, 0.0][..])
+                .unwrap();
+            let tie_point = [0.0, 0.0, 0.0, 20.0, 10.0, 0.0];
+            tags.write_tag(Tag::ModelTiepointTag, &tie_point[..])
+                .unwrap();
+            tags.write_tag(Tag::GdalNodata, "-3.40282265508890445e+38")
+                .unwrap();
+        }
+        image.write_data(samples).unwrap();
+        bytes.into_inner()
+    }
+
+    fn direction(latitude: f32, longitude: f32) -> Vec3 {
+        geographic_direction(latitude.to_radians(), longitude.to_radians())
+    }
+
+    #[test]
+    fn reads_geotiff_files() {
+        let samples = [1.0, 2.0, 3.0, NO_DATA, 5.0, 6.0, 7.0, 8.0];
+        let map = read_height_map(&geotiff(&samples, true)).unwrap();
+        assert_eq!(map.sample(direction(9.5, 20.5), 0.0), Some(1.0));
+        assert_eq!(map.sample(direction(8.5, 23.5), 0.0), Some(8.0));
+        assert_eq!(map.sample(direction(9.5, 23.5), 0.0), None);
+        assert_eq!(map.sample(direction(9.5, 25.0), 0.0), None);
+        assert_eq!(map.get_height_at(direction(30.0, 20.5), 0.0), 0.0);
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_tiff() {
+        assert!(read_height_map(b"PDS_VERSION_ID = PDS3").is_err());
+        assert!(read_height_map(&[]).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = geotiff(&[0.0; 8], true);
+        assert!(read_height_map(&bytes[..bytes.len() / 2]).is_err());
+        assert!(read_height_map(&bytes[..8]).is_err());
+    }
+
+    #[test]
+    fn rejects_images_without_georeferencing() {
+        assert!(read_height_map(&geotiff(&[0.0; 8], false)).is_err());
+    }
+}
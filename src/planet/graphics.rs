@@ -5,7 +5,7 @@ use bevy::{
     reflect::TypeUuid,
     render::{
         mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
-        render_asset::{PrepareAssetError, RenderAsset},
+        render_asset::{PrepareAssetError, RenderAsset, RenderAssets},
         render_resource::{
             std140::{AsStd140, Std140},
             *,
@@ -16,13 +16,26 @@ use bevy::{
 
 pub const ATTRIBUTE_ALTITUDE: MeshVertexAttribute =
     MeshVertexAttribute::new("Altitude", 319173648, VertexFormat::Float32);
+/// Subdivision step that created a vertex, relative to the depth of the mesh.
+pub const ATTRIBUTE_SUBDIVISION_LEVEL: MeshVertexAttribute =
+    MeshVertexAttribute::new("SubdivisionLevel", 802461197, VertexFormat::Float32);
 
-#[derive(Default, Debug, Clone, AsStd140, TypeUuid)]
+#[derive(Default, Debug, Clone, TypeUuid)]
 #[uuid = "679eb5cb-7d9e-4c62-93b0-3abb2ba9c542"]
 pub struct PlanetMaterial {
     pub max_altitude: f32,
     pub min_altitude: f32,
     pub color_mode: u32,
+    pub color_ramp: Handle<Image>,
+    pub normal_map: Option<Handle<Image>>,
+}
+
+#[derive(Clone, AsStd140)]
+pub struct PlanetMaterialUniformData {
+    pub max_altitude: f32,
+    pub min_altitude: f32,
+    pub color_mode: u32,
+    pub has_normal_map: u32,
 }
 
 #[derive(Clone)]
@@ -36,6 +49,172 @@ impl RenderAsset for PlanetMaterial {
 
     type PreparedAsset = GpuCustomMaterial;
 
+    type Param = (
+        SRes<RenderDevice>,
+        SRes<MaterialPipeline<Self>>,
+        SRes<RenderAssets<Image>>,
+    );
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        extract_asset: Self::ExtractedAsset,
+        (render_device, material_pipeline, gpu_images): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let color_ramp = match gpu_images.get(&extract_asset.color_ramp) {
+            Some(gpu_image) => gpu_image,
+            None => return Err(PrepareAssetError::RetryNextUpdate(extract_asset)),
+        };
+        let (normal_map_texture_view, normal_map_sampler) = match material_pipeline
+            .mesh_pipeline
+            .get_image_texture(gpu_images, &extract_asset.normal_map)
+        {
+            Some(result) => result,
+            None => return Err(PrepareAssetError::RetryNextUpdate(extract_asset)),
+        };
+
+        let value = PlanetMaterialUniformData {
+            max_altitude: extract_asset.max_altitude,
+            min_altitude: extract_asset.min_altitude,
+            color_mode: extract_asset.color_mode,
+            has_normal_map: extract_asset.normal_map.is_some() as u32,
+        };
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: None,
+            contents: value.as_std140().as_bytes(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&color_ramp.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&color_ramp.sampler),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(normal_map_texture_view),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::Sampler(normal_map_sampler),
+                },
+            ],
+            layout: &material_pipeline.material_layout,
+        });
+
+        Ok(GpuCustomMaterial {
+            bind_group,
+            _buffer: buffer,
+        })
+    }
+}
+
+impl Material for PlanetMaterial {
+    fn vertex_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/custom_shader.wgsl"))
+    }
+
+    fn fragment_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/custom_shader.wgsl"))
+    }
+
+    fn bind_group(gpu_material: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &gpu_material.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(
+                            PlanetMaterialUniformData::std140_size_static() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D1,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn specialize(
+        _: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            ATTRIBUTE_ALTITUDE.at_shader_location(2),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(3),
+            Mesh::ATTRIBUTE_TANGENT.at_shader_location(4),
+            ATTRIBUTE_SUBDIVISION_LEVEL.at_shader_location(5),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Clone, AsStd140, TypeUuid)]
+#[uuid = "3c1f7b7e-52a4-4a8e-9d0e-6f5b2a9c1d84"]
+pub struct GraticuleMaterial {
+    pub spacing: f32,
+    pub line_width: f32,
+}
+
+impl RenderAsset for GraticuleMaterial {
+    type ExtractedAsset = Self;
+
+    type PreparedAsset = GpuCustomMaterial;
+
     type Param = (SRes<RenderDevice>, SRes<MaterialPipeline<Self>>);
 
     fn extract_asset(&self) -> Self::ExtractedAsset {
@@ -67,13 +246,13 @@ impl RenderAsset for PlanetMaterial {
     }
 }
 
-impl Material for PlanetMaterial {
+impl Material for GraticuleMaterial {
     fn vertex_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
-        return Some(asset_server.load("shaders/custom_shader.wgsl"));
+        Some(asset_server.load("shaders/graticule.wgsl"))
     }
 
     fn fragment_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
-        return Some(asset_server.load("shaders/custom_shader.wgsl"));
+        Some(asset_server.load("shaders/graticule.wgsl"))
     }
 
     fn bind_group(gpu_material: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
@@ -85,11 +264,226 @@ impl Material for PlanetMaterial {
             label: None,
             entries: &[BindGroupLayoutEntry {
                 binding: 0,
-                visibility: ShaderStages::VERTEX,
+                visibility: ShaderStages::FRAGMENT,
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(PlanetMaterial::std140_size_static() as u64),
+                    min_binding_size: BufferSize::new(
+                        GraticuleMaterial::std140_size_static() as u64
+                    ),
+                },
+                count: None,
+            }],
+        })
+    }
+
+    fn alpha_mode(_: &<Self as RenderAsset>::PreparedAsset) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[Mesh::ATTRIBUTE_POSITION.at_shader_location(0)])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        // The graticule is drawn over the planet mesh itself, so it must pass
+        // the depth test against the surface it lies on.
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.depth_compare = CompareFunction::GreaterEqual;
+        }
+        Ok(())
+    }
+}
+
+/// Scattering parameters of an atmosphere shell, all the lengths in metres.
+#[derive(Default, Debug, Clone, AsStd140, TypeUuid)]
+#[uuid = "b5e0d3a1-6f2c-4c9b-8e47-2d1a9f3c7e55"]
+pub struct AtmosphereMaterial {
+    pub rayleigh_scattering: Vec3,
+    pub rayleigh_scale_height: f32,
+    pub mie_scattering: Vec3,
+    pub mie_scale_height: f32,
+    pub mie_absorption: Vec3,
+    pub planet_radius: f32,
+    pub mie_asymmetry: Vec3,
+    pub atmosphere_radius: f32,
+    /// Direction toward the sun, in world space.
+    pub sun_direction: Vec3,
+    pub sun_intensity: f32,
+}
+
+impl RenderAsset for AtmosphereMaterial {
+    type ExtractedAsset = Self;
+
+    type PreparedAsset = GpuCustomMaterial;
+
+    type Param = (SRes<RenderDevice>, SRes<MaterialPipeline<Self>>);
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        extract_asset: Self::ExtractedAsset,
+        (render_device, material_pipeline): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: None,
+            contents: extract_asset.as_std140().as_bytes(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            layout: &material_pipeline.material_layout,
+        });
+
+        Ok(GpuCustomMaterial {
+            bind_group,
+            _buffer: buffer,
+        })
+    }
+}
+
+impl Material for AtmosphereMaterial {
+    fn vertex_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/atmosphere.wgsl"))
+    }
+
+    fn fragment_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/atmosphere.wgsl"))
+    }
+
+    fn bind_group(gpu_material: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &gpu_material.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(
+                        AtmosphereMaterial::std140_size_static() as u64
+                    ),
+                },
+                count: None,
+            }],
+        })
+    }
+
+    fn alpha_mode(_: &<Self as RenderAsset>::PreparedAsset) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[Mesh::ATTRIBUTE_POSITION.at_shader_location(0)])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        // Only the far side of the shell is drawn, whether the camera is in
+        // space or on the surface, and the shader finds where the view ray
+        // enters the atmosphere and hits the ground by itself. It gives its
+        // fragments the depth of the entry point, so that they are tested
+        // against the bodies in front of the atmosphere but not the ground.
+        descriptor.primitive.cull_mode = Some(Face::Front);
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.depth_compare = CompareFunction::GreaterEqual;
+        }
+        // The shader outputs the in-scattered light premultiplied by its
+        // coverage, which is the opacity of the atmosphere along the ray.
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            for target in fragment.targets.iter_mut() {
+                target.blend = Some(BlendState {
+                    color: BlendComponent {
+                        src_factor: BlendFactor::One,
+                        dst_factor: BlendFactor::OneMinusSrcAlpha,
+                        operation: BlendOperation::Add,
+                    },
+                    alpha: BlendComponent::OVER,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Clone, AsStd140, TypeUuid)]
+#[uuid = "0d6c9a52-8f3e-4b1d-a7c2-5e9f41b3d6a8"]
+pub struct StarsMaterial {
+    pub brightness: f32,
+}
+
+impl RenderAsset for StarsMaterial {
+    type ExtractedAsset = Self;
+
+    type PreparedAsset = GpuCustomMaterial;
+
+    type Param = (SRes<RenderDevice>, SRes<MaterialPipeline<Self>>);
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        extract_asset: Self::ExtractedAsset,
+        (render_device, material_pipeline): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: None,
+            contents: extract_asset.as_std140().as_bytes(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            layout: &material_pipeline.material_layout,
+        });
+
+        Ok(GpuCustomMaterial {
+            bind_group,
+            _buffer: buffer,
+        })
+    }
+}
+
+impl Material for StarsMaterial {
+    fn vertex_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/stars.wgsl"))
+    }
+
+    fn fragment_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load("shaders/stars.wgsl"))
+    }
+
+    fn bind_group(gpu_material: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &gpu_material.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(StarsMaterial::std140_size_static() as u64),
                 },
                 count: None,
             }],
@@ -103,10 +497,15 @@ impl Material for PlanetMaterial {
     ) -> Result<(), SpecializedMeshPipelineError> {
         let vertex_layout = layout.get_layout(&[
             Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
-            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
-            ATTRIBUTE_ALTITUDE.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(1),
         ])?;
         descriptor.vertex.buffers = vec![vertex_layout];
+        // The stars lie at infinity, on the far plane where the depth buffer
+        // is cleared, and must only show where nothing else was drawn.
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.depth_compare = CompareFunction::GreaterEqual;
+            depth_stencil.depth_write_enabled = false;
+        }
         Ok(())
     }
 }
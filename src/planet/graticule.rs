@@ -0,0 +1,213 @@
+use super::{
+    graphics::GraticuleMaterial,
+    height_map::{geographic_coord, geographic_direction},
+    Planet,
+};
+use crate::player::PlayerCamera;
+use bevy::{pbr::NotShadowCaster, prelude::*};
+
+const SPACINGS: [f32; 6] = [1.0, 5.0, 10.0, 15.0, 30.0, 45.0];
+const DEFAULT_SPACING: usize = 3;
+const LINE_WIDTH: f32 = 1.5;
+const MAX_LABELS: usize = 48;
+const FONT_SIZE: f32 = 12.0;
+
+/// Latitude/longitude grid drawn over the planets, toggled at runtime.
+pub struct Graticule {
+    visible: bool,
+    spacing: usize,
+    material: Handle<GraticuleMaterial>,
+}
+
+#[derive(Component)]
+pub struct GraticuleOverlay;
+
+#[derive(Component)]
+pub struct GraticuleLabel;
+
+impl Graticule {
+    fn spacing(&self) -> f32 {
+        SPACINGS[self.spacing]
+    }
+}
+
+pub fn setup_graticule(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<GraticuleMaterial>>,
+) {
+    let material = materials.add(GraticuleMaterial {
+        spacing: SPACINGS[DEFAULT_SPACING],
+        line_width: LINE_WIDTH,
+    });
+    commands.insert_resource(Graticule {
+        visible: false,
+        spacing: DEFAULT_SPACING,
+        material,
+    });
+
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    for _ in 0..MAX_LABELS {
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: FONT_SIZE,
+                        color: Color::rgba(0.9, 0.9, 0.9, 0.8),
+                    },
+                    default(),
+                ),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(GraticuleLabel);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn spawn_graticule_overlay(
+    mut commands: Commands,
+    graticule: Res<Graticule>,
+    planets: Query<
+        (Entity, &Handle<Mesh>, Option<&Children>),
+        (With<Planet>, Changed<Handle<Mesh>>),
+    >,
+    mut overlays: Query<&mut Handle<Mesh>, (With<GraticuleOverlay>, Without<Planet>)>,
+) {
+    for (entity, mesh, children) in planets.iter() {
+        // A rebuilt planet keeps its overlay, which only needs the new mesh.
+        let overlay = children
+            .into_iter()
+            .flat_map(|children| children.iter())
+            .find(|child| overlays.get(**child).is_ok());
+        if let Some(overlay) = overlay {
+            *overlays.get_mut(*overlay).unwrap() = mesh.clone();
+            continue;
+        }
+
+        commands.entity(entity).with_children(|children| {
+            children
+                .spawn_bundle(MaterialMeshBundle {
+                    mesh: mesh.clone(),
+                    material: graticule.material.clone(),
+                    visibility: Visibility {
+                        is_visible: graticule.visible,
+                    },
+                    ..default()
+                })
+                .insert(GraticuleOverlay)
+                .insert(NotShadowCaster);
+        });
+    }
+}
+
+pub fn graticule_control(keyboard: Res<Input<KeyCode>>, mut graticule: ResMut<Graticule>) {
+    if keyboard.just_pressed(KeyCode::G) {
+        graticule.visible = !graticule.visible;
+    }
+    if keyboard.just_pressed(KeyCode::LBracket) && graticule.spacing > 0 {
+        graticule.spacing -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::RBracket) && graticule.spacing < SPACINGS.len() - 1 {
+        graticule.spacing += 1;
+    }
+}
+
+pub fn update_graticule(
+    graticule: Res<Graticule>,
+    mut materials: ResMut<Assets<GraticuleMaterial>>,
+    mut overlays: Query<&mut Visibility, With<GraticuleOverlay>>,
+) {
+    if graticule.is_changed() {
+        for mut visibility in overlays.iter_mut() {
+            visibility.is_visible = graticule.visible;
+        }
+        if let Some(material) = materials.get_mut(graticule.material.clone()) {
+            if material.spacing != graticule.spacing() {
+                material.spacing = graticule.spacing();
+            }
+        }
+    }
+}
+
+pub fn update_graticule_labels(
+    graticule: Res<Graticule>,
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    camera: Query<(&GlobalTransform, &Camera), With<PlayerCamera>>,
+    planets: Query<(&Planet, &GlobalTransform), With<Handle<Mesh>>>,
+    mut labels: Query<(&mut Style, &mut Text, &mut Visibility), With<GraticuleLabel>>,
+) {
+    let mut intersections = vec![];
+    let window_size = windows
+        .get_primary()
+        .map(|window| Vec2::new(window.width(), window.height()))
+        .unwrap_or_default();
+    if let (true, Ok((camera_transform, camera))) = (graticule.visible, camera.get_single()) {
+        let spacing = graticule.spacing().to_radians();
+        let rows = (180.0 / graticule.spacing()) as i32;
+        let columns = (360.0 / graticule.spacing()) as i32;
+        let on_screen = |screen_position: &Vec2| {
+            screen_position.cmpge(Vec2::ZERO).all() && screen_position.cmple(window_size).all()
+        };
+
+        for (planet, transform) in planets.iter() {
+            let model = transform.compute_matrix();
+            for row in -rows / 2..=rows / 2 {
+                for column in 0..columns {
+                    // All the meridians meet at the poles, label them once.
+                    if row.abs() * 2 == rows && column > 0 {
+                        continue;
+                    }
+                    let direction =
+                        geographic_direction(row as f32 * spacing, column as f32 * spacing);
+                    let position = model.transform_point3(direction * planet.radius);
+                    let normal = model.transform_vector3(direction);
+                    if normal.dot(camera_transform.translation - position) <= 0.0 {
+                        continue;
+                    }
+                    let screen_position =
+                        camera.world_to_screen(&windows, &images, camera_transform, position);
+                    if let Some(screen_position) = screen_position.filter(on_screen) {
+                        let distance = camera_transform.translation.distance(position);
+                        intersections.push((distance, screen_position, direction));
+                    }
+                }
+            }
+        }
+        intersections.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    let mut intersections = intersections.into_iter();
+    for (mut style, mut text, mut visibility) in labels.iter_mut() {
+        if let Some((_, screen_position, direction)) = intersections.next() {
+            style.position = Rect {
+                left: Val::Px(screen_position.x + 2.0),
+                bottom: Val::Px(screen_position.y + 2.0),
+                ..default()
+            };
+            text.sections[0].value = format_coordinates(direction);
+            visibility.is_visible = true;
+        } else if visibility.is_visible {
+            visibility.is_visible = false;
+        }
+    }
+}
+
+fn format_coordinates(direction: Vec3) -> String {
+    let (latitude, longitude) = geographic_coord(direction);
+    let latitude = latitude.to_degrees().round();
+    let longitude = longitude.to_degrees().round() % 360.0;
+    let latitude = match latitude {
+        l if l > 0.0 => format!("{}°N", l),
+        l if l < 0.0 => format!("{}°S", -l),
+        _ => "0°".to_string(),
+    };
+    format!("{} {}°E", latitude, longitude)
+}
@@ -1,20 +1,96 @@
+use super::{
+    cache,
+    load_errors::HeightMapErrors,
+    pds::Pds3HeightMapLoader,
+    projection::{Bounds, Projection},
+    pyramid::Pyramid,
+};
+use anyhow::bail;
 use bevy::{
     asset::{AssetLoader, LoadContext, LoadedAsset},
     prelude::*,
     reflect::TypeUuid,
     utils::BoxedFuture,
 };
-use std::f32::consts::{FRAC_PI_2, PI};
+use memmap2::Mmap;
+use std::{
+    collections::hash_map::DefaultHasher,
+    f32::consts::{FRAC_PI_2, PI},
+    fs::File,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 const SAMPLE_DIST: f32 = 0.001;
+/// Default vertical exaggeration of the relief.
 pub const HEIGHT_SCALLING: f32 = 10.0;
 
-#[derive(Debug, TypeUuid)]
+/// Cheap to clone, its samples and coarser versions being shared.
+#[derive(Debug, Clone, TypeUuid)]
 #[uuid = "f8a947d6-7b52-4707-bb6c-9c295c9ef3dd"]
 pub struct HeightMap {
     width: usize,
     height: usize,
-    data: Vec<i16>,
+    data: Arc<Samples>,
+    /// Height in metres of a sample `v`: `v * scale + offset`.
+    scale: f32,
+    offset: f32,
+    /// Sample value of the holes of the map, NaN samples always being holes.
+    no_data: Option<f32>,
+    projection: Projection,
+    bounds: Bounds,
+    /// Coarser versions of the map, sampled from afar.
+    pyramid: Arc<Pyramid>,
+    /// Hash of the samples as read from their file, or of the file itself when
+    /// it is mapped.
+    source: u64,
+}
+
+/// Samples of a height map, row after row from the north-west corner.
+#[derive(Debug)]
+pub enum Samples {
+    I16(Vec<i16>),
+    U16(Vec<u16>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    Mapped(MappedSamples),
+}
+
+/// Samples of a file mapped in memory, decoded as they are read, so that
+/// only the parts of the file being sampled are loaded.
+#[derive(Debug)]
+pub struct MappedSamples {
+    map: Mmap,
+    path: PathBuf,
+    /// Hash of the path, size and modification time of the file and of where
+    /// its samples are, which changes with them.
+    identity: u64,
+    /// Position of the first sample in the file, in bytes.
+    start: usize,
+    sample_type: SampleType,
+    byte_order: ByteOrder,
+}
+
+/// Type of the samples in a height map file.
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum SampleType {
+    I16,
+    U16,
+    I32,
+    F32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum ByteOrder {
+    BigEndian,
+    LittleEndian,
+}
+
+/// Height maps of a planet, each one replacing the previous ones where it
+/// has samples, like polar tiles over a global map.
+pub struct HeightMapTiles<'a> {
+    maps: Vec<&'a HeightMap>,
 }
 
 struct Neighboors {
@@ -37,7 +113,7 @@ impl Neighboors {
     }
 
     fn linear_interpolation(v1: f32, v2: f32, t: f32) -> f32 {
-        v1 * t + (1.0 - t) * v2
+        v1 * (1.0 - t) + v2 * t
     }
 
     fn nearest(&self) -> f32 {
@@ -57,51 +133,456 @@ impl Neighboors {
     }
 }
 
+impl SampleType {
+    /// Size of a sample, in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::F32 => 4,
+        }
+    }
+
+    /// Size of `count` samples, failing if `available` bytes cannot hold them.
+    fn check_size(&self, available: usize, count: usize) -> anyhow::Result<usize> {
+        let size = count * self.size();
+        if available < size {
+            bail!(
+                "{} bytes hold less than {} {:?} samples, that need {}",
+                available,
+                count,
+                self,
+                size
+            );
+        }
+        Ok(size)
+    }
+}
+
+impl Samples {
+    /// Decodes the first `count` samples of `bytes`.
+    pub fn from_bytes(
+        bytes: &[u8],
+        count: usize,
+        sample_type: SampleType,
+        byte_order: ByteOrder,
+    ) -> anyhow::Result<Self> {
+        let size = sample_type.check_size(bytes.len(), count)?;
+        let bytes = &bytes[..size];
+        Ok(match sample_type {
+            SampleType::I16 => Self::I16(decode(
+                bytes,
+                byte_order,
+                i16::from_be_bytes,
+                i16::from_le_bytes,
+            )),
+            SampleType::U16 => Self::U16(decode(
+                bytes,
+                byte_order,
+                u16::from_be_bytes,
+                u16::from_le_bytes,
+            )),
+            SampleType::I32 => Self::I32(decode(
+                bytes,
+                byte_order,
+                i32::from_be_bytes,
+                i32::from_le_bytes,
+            )),
+            SampleType::F32 => Self::F32(decode(
+                bytes,
+                byte_order,
+                f32::from_be_bytes,
+                f32::from_le_bytes,
+            )),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::I16(samples) => samples.len(),
+            Self::U16(samples) => samples.len(),
+            Self::I32(samples) => samples.len(),
+            Self::F32(samples) => samples.len(),
+            Self::Mapped(samples) => samples.len(),
+        }
+    }
+
+    /// Hash of the samples as they were decoded from their file, or of the
+    /// identity of the file they are mapped from, without reading it.
+    fn identity(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        std::mem::discriminant(self).hash(&mut hasher);
+        match self {
+            Self::I16(samples) => samples.hash(&mut hasher),
+            Self::U16(samples) => samples.hash(&mut hasher),
+            Self::I32(samples) => samples.hash(&mut hasher),
+            Self::F32(samples) => {
+                for sample in samples {
+                    sample.to_bits().hash(&mut hasher);
+                }
+            }
+            Self::Mapped(samples) => samples.identity.hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+
+    fn get(&self, index: usize) -> f32 {
+        match self {
+            Self::I16(samples) => samples[index] as f32,
+            Self::U16(samples) => samples[index] as f32,
+            Self::I32(samples) => samples[index] as f32,
+            Self::F32(samples) => samples[index],
+            Self::Mapped(samples) => samples.get(index),
+        }
+    }
+}
+
+impl MappedSamples {
+    /// Maps the `count` samples of a file starting at the byte `start`.
+    pub fn open(
+        path: &Path,
+        start: usize,
+        count: usize,
+        sample_type: SampleType,
+        byte_order: ByteOrder,
+    ) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the asset files are not expected to change while they are
+        // explored.
+        let map = unsafe { Mmap::map(&file)? };
+        sample_type.check_size(map.len().saturating_sub(start), count)?;
+
+        let metadata = file.metadata()?;
+        let mut hasher = DefaultHasher::new();
+        (path, metadata.len(), metadata.modified().ok()).hash(&mut hasher);
+        (start, sample_type, byte_order).hash(&mut hasher);
+        Ok(Self {
+            map,
+            path: path.to_owned(),
+            identity: hasher.finish(),
+            start,
+            sample_type,
+            byte_order,
+        })
+    }
+
+    /// Pyramid of the samples, read from the cache, so that the file is only
+    /// read where it is sampled, or built and cached on the first run.
+    fn pyramid(&self, width: usize, height: usize, no_data: Option<f32>) -> Pyramid {
+        let mut hasher = DefaultHasher::new();
+        self.path.hash(&mut hasher);
+        let name = format!("pyramid_{:016x}", hasher.finish());
+        let mut hasher = DefaultHasher::new();
+        (self.identity, width, height, no_data.map(f32::to_bits)).hash(&mut hasher);
+        let key = hasher.finish();
+
+        cache::load_pyramid(&name, key).unwrap_or_else(|| {
+            let pyramid = Pyramid::build(width, height, |index| {
+                Some(self.get(index)).filter(|value| !is_hole(*value, no_data))
+            });
+            cache::save_pyramid(&name, key, &pyramid);
+            pyramid
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.map.len().saturating_sub(self.start) / self.sample_type.size()
+    }
+
+    fn get(&self, index: usize) -> f32 {
+        let start = self.start + index * self.sample_type.size();
+        let bytes = &self.map[start..];
+        let byte_order = self.byte_order;
+        match self.sample_type {
+            SampleType::I16 => {
+                from_bytes(bytes, byte_order, i16::from_be_bytes, i16::from_le_bytes) as f32
+            }
+            SampleType::U16 => {
+                from_bytes(bytes, byte_order, u16::from_be_bytes, u16::from_le_bytes) as f32
+            }
+            SampleType::I32 => {
+                from_bytes(bytes, byte_order, i32::from_be_bytes, i32::from_le_bytes) as f32
+            }
+            SampleType::F32 => {
+                from_bytes(bytes, byte_order, f32::from_be_bytes, f32::from_le_bytes)
+            }
+        }
+    }
+}
+
+fn decode<T, const N: usize>(
+    bytes: &[u8],
+    byte_order: ByteOrder,
+    from_be_bytes: fn([u8; N]) -> T,
+    from_le_bytes: fn([u8; N]) -> T,
+) -> Vec<T> {
+    bytes
+        .chunks_exact(N)
+        .map(|chunk| from_bytes(chunk, byte_order, from_be_bytes, from_le_bytes))
+        .collect()
+}
+
+/// Decodes the sample at the start of `bytes`.
+fn from_bytes<T, const N: usize>(
+    bytes: &[u8],
+    byte_order: ByteOrder,
+    from_be_bytes: fn([u8; N]) -> T,
+    from_le_bytes: fn([u8; N]) -> T,
+) -> T {
+    let bytes = bytes[..N].try_into().unwrap();
+    match byte_order {
+        ByteOrder::BigEndian => from_be_bytes(bytes),
+        ByteOrder::LittleEndian => from_le_bytes(bytes),
+    }
+}
+
 impl HeightMap {
-    pub fn fetch_relief_at(&self, normalized_position: Vec3, radius: f32) -> (f32, Vec3) {
-        let height = self.get_height_at(normalized_position);
-        let normal = self.get_norm((radius + height) * normalized_position, radius);
-        (height, normal)
+    /// Height map whose samples equal to `no_data` are holes, building its
+    /// coarser versions.
+    pub fn new(
+        width: usize,
+        height: usize,
+        data: Samples,
+        no_data: Option<f32>,
+        projection: Projection,
+    ) -> anyhow::Result<Self> {
+        if width == 0 || height == 0 {
+            bail!("Empty {} x {} height map", width, height);
+        }
+        if data.len() < width * height {
+            bail!(
+                "{} samples for a {} x {} height map",
+                data.len(),
+                width,
+                height
+            );
+        }
+        let bounds = projection.bounds(width, height);
+        let pyramid = match &data {
+            Samples::Mapped(samples) => samples.pyramid(width, height, no_data),
+            _ => Pyramid::build(width, height, |index| {
+                Some(data.get(index)).filter(|value| !is_hole(*value, no_data))
+            }),
+        };
+        let source = data.identity();
+        Ok(Self {
+            width,
+            height,
+            data: Arc::new(data),
+            scale: 1.0,
+            offset: 0.0,
+            no_data,
+            projection,
+            bounds,
+            pyramid: Arc::new(pyramid),
+            source,
+        })
+    }
+
+    pub fn with_scaling(mut self, scale: f32, offset: f32) -> Self {
+        self.scale = scale;
+        self.offset = offset;
+        self
+    }
+
+    /// Hash of the samples of the map, of their meaning and of where they lie,
+    /// changing with its files.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.source, self.width, self.height).hash(&mut hasher);
+        (self.scale.to_bits(), self.offset.to_bits()).hash(&mut hasher);
+        self.no_data.map(f32::to_bits).hash(&mut hasher);
+        format!("{:?}", self.projection).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Lowest and highest heights of the map, `None` if it is all holes.
+    pub fn height_range(&self) -> Option<(f32, f32)> {
+        let (min, max) = self.pyramid.range()?;
+        let (min, max) = (
+            min * self.scale + self.offset,
+            max * self.scale + self.offset,
+        );
+        Some((min.min(max), min.max(max)))
+    }
+
+    pub fn log_loaded(&self, path: &Path) {
+        let range = match self.height_range() {
+            Some((min, max)) => format!("heights from {:.0} m to {:.0} m", min, max),
+            None => "no heights".to_string(),
+        };
+        info!(
+            "Loaded {} x {} height map {:?} covering {}, {}",
+            self.width, self.height, path, self.bounds, range
+        );
     }
 
-    pub fn get_height_at(&self, normalized_position: Vec3) -> f32 {
-        let (longitude, latitude) = self.get_spherical_coord(normalized_position);
-        let neghboors = self.get_neighboors(longitude, latitude);
+    /// Height above the reference radius, in metres, or `None` outside of the
+    /// map and in its holes, averaged over the `footprint`, in radians.
+    pub fn sample(&self, normalized_position: Vec3, footprint: f32) -> Option<f32> {
+        let (latitude, longitude) = geographic_coord(normalized_position);
+        if !self.bounds.contains_latitude(latitude) {
+            return None;
+        }
+        let (column, row) = self.projection.pixel_at(latitude, longitude);
+        let inside = |position: f32, size: usize| (-0.5..size as f32 - 0.5).contains(&position);
+        let covered = self.projection.wraps_around(self.width)
+            || (inside(column, self.width) && inside(row, self.height));
+        if !covered {
+            return None;
+        }
+
+        // Samples averaged along each side, a power of two.
+        let factor = (footprint / self.projection.resolution()).max(1.0);
+        let factor = 1 << (factor.log2().floor() as u32).min(30);
+        let value = if factor == 1 {
+            get_neighboors(column, row, |row, col| self.height_at(row, col)).nearest()
+        } else {
+            self.filtered_at(column, row, factor)
+        };
+        if self.is_hole(value) {
+            return None;
+        }
+        Some(value * self.scale + self.offset)
+    }
 
-        HEIGHT_SCALLING * neghboors.nearest()
+    fn is_hole(&self, value: f32) -> bool {
+        is_hole(value, self.no_data)
     }
 
-    fn get_neighboors(&self, longitude: f32, latitude: f32) -> Neighboors {
-        let lo_f = longitude.floor();
-        let lo_c = longitude.ceil();
-        let la_f = latitude.floor();
-        let la_c = latitude.ceil();
+    /// Mean of the samples around a pixel position, over `factor` samples
+    /// along each side, NaN if they are all holes.
+    fn filtered_at(&self, column: f32, row: f32, factor: usize) -> f32 {
+        let level = match self.pyramid.level(factor) {
+            Some(level) => level,
+            None => return self.mean_at(column, row, factor),
+        };
+        // Cell positions count from 0 at the center of the first cell too.
+        let to_cell = |position: f32| (position + 0.5) / level.factor as f32 - 0.5;
+        let wraps = self.projection.wraps_around(self.width);
+        let neighboors = get_neighboors(to_cell(column), to_cell(row), |row, col| {
+            level.mean(grid_index(row, col, level.width, level.height, wraps))
+        });
+        let value = neighboors.bilinear_interpolation();
+        if value.is_nan() {
+            neighboors.nearest()
+        } else {
+            value
+        }
+    }
 
-        Neighboors {
-            bottom_left: self.height_at(la_f as usize, lo_f as usize),
-            bottom_right: self.height_at(la_f as usize, lo_c as usize),
-            top_left: self.height_at(la_c as usize, lo_f as usize),
-            top_right: self.height_at(la_c as usize, lo_c as usize),
-            relative_lat: latitude - la_f,
-            relative_lon: longitude - lo_f,
+    /// Mean of the `factor` x `factor` samples centered on a pixel position.
+    fn mean_at(&self, column: f32, row: f32, factor: usize) -> f32 {
+        let first = |position: f32| (position - (factor - 1) as f32 / 2.0).round() as isize;
+        let (first_column, first_row) = (first(column), first(row));
+        let (mut sum, mut count) = (0.0, 0);
+        for row in first_row..first_row + factor as isize {
+            for column in first_column..first_column + factor as isize {
+                let value = self.height_at(row, column);
+                if !self.is_hole(value) {
+                    sum += value;
+                    count += 1;
+                }
+            }
+        }
+        if count > 0 {
+            sum / count as f32
+        } else {
+            f32::NAN
         }
     }
 
-    fn get_spherical_coord(&self, normalized_position: Vec3) -> (f32, f32) {
-        let longitude = normalized_position.x.atan2(normalized_position.z);
-        let latitude = -normalized_position.y.asin();
-        let longitude = ((longitude + PI) / (2.0 * PI)) * self.width as f32;
-        let latitude = ((latitude + FRAC_PI_2) / PI) * self.height as f32;
-        (longitude, latitude)
+    fn height_at(&self, row: isize, col: isize) -> f32 {
+        let wraps = self.projection.wraps_around(self.width);
+        self.data
+            .get(grid_index(row, col, self.width, self.height, wraps))
     }
+}
+
+fn is_hole(value: f32, no_data: Option<f32>) -> bool {
+    value.is_nan() || Some(value) == no_data
+}
+
+fn get_neighboors(column: f32, row: f32, value_at: impl Fn(isize, isize) -> f32) -> Neighboors {
+    let lo_f = column.floor();
+    let lo_c = column.ceil();
+    let la_f = row.floor();
+    let la_c = row.ceil();
 
-    fn height_at(&self, row: usize, col: usize) -> f32 {
-        let row = row % self.height;
-        let col = col.clamp(0, self.width - 1);
-        self.data[row * self.width + col] as f32
+    Neighboors {
+        bottom_left: value_at(la_f as isize, lo_f as isize),
+        bottom_right: value_at(la_f as isize, lo_c as isize),
+        top_left: value_at(la_c as isize, lo_f as isize),
+        top_right: value_at(la_c as isize, lo_c as isize),
+        relative_lat: row - la_f,
+        relative_lon: column - lo_f,
+    }
+}
+
+/// Index of a sample in a grid, the rows clamped and the columns wrapped
+/// around the planet or clamped.
+fn grid_index(row: isize, col: isize, width: usize, height: usize, wraps: bool) -> usize {
+    let row = row.clamp(0, height as isize - 1) as usize;
+    let col = if wraps {
+        col.rem_euclid(width as isize) as usize
+    } else {
+        col.clamp(0, width as isize - 1) as usize
+    };
+    row * width + col
+}
+
+impl Relief for HeightMap {
+    fn get_height_at(&self, normalized_position: Vec3, footprint: f32) -> f32 {
+        self.sample(normalized_position, footprint).unwrap_or(0.0)
     }
 
-    fn get_norm(&self, pos: Vec3, radius: f32) -> Vec3 {
+    fn resolution(&self) -> f32 {
+        self.projection.resolution()
+    }
+}
+
+impl<'a> HeightMapTiles<'a> {
+    /// The first map gives the resolution of the whole relief.
+    pub fn new(maps: Vec<&'a HeightMap>) -> Self {
+        Self { maps }
+    }
+}
+
+impl<'a> Relief for HeightMapTiles<'a> {
+    fn get_height_at(&self, normalized_position: Vec3, footprint: f32) -> f32 {
+        self.maps
+            .iter()
+            .rev()
+            .find_map(|map| map.sample(normalized_position, footprint))
+            .unwrap_or(0.0)
+    }
+
+    fn resolution(&self) -> f32 {
+        self.maps[0].resolution()
+    }
+}
+
+/// Surface elevation of a planet, sampled by direction from its center.
+pub trait Relief {
+    /// Height above the reference radius, in metres, at the level of detail
+    /// of the `footprint`: the angular size, in radians, of the surface the
+    /// height stands for, 0 for the finest detail.
+    fn get_height_at(&self, normalized_position: Vec3, footprint: f32) -> f32;
+
+    /// Angular size of the finest detail of the relief, in radians.
+    fn resolution(&self) -> f32;
+
+    fn fetch_relief_at(
+        &self,
+        normalized_position: Vec3,
+        radius: f32,
+        footprint: f32,
+    ) -> (f32, Vec3) {
+        let height = self.get_height_at(normalized_position, footprint);
+        let normal = self.get_norm((radius + height) * normalized_position, radius, footprint);
+        (height, normal)
+    }
+
+    fn get_norm(&self, pos: Vec3, radius: f32, footprint: f32) -> Vec3 {
         let cross = Vec3::Z.cross(pos.normalize());
         let rotation = if cross.length() > 0.0 {
             let angle = Vec3::Z.dot(pos.normalize()).acos();
@@ -110,21 +591,77 @@ impl HeightMap {
             Quat::IDENTITY
         };
 
-        let samples: Vec<Vec3> = vec![Vec3::X, Vec3::Y]
+        let samples: Vec<Vec3> = [Vec3::X, Vec3::Y]
             .iter()
             .map(|v| rotation * *v)
             .map(|v| (pos + v * radius * SAMPLE_DIST).normalize())
-            .map(|v| (radius + self.get_height_at(v)) * v)
+            .map(|v| (radius + self.get_height_at(v, footprint)) * v)
             .map(|v| v - pos)
             .collect();
 
-        let norm = samples[0].cross(samples[1]).normalize();
-        norm
+        samples[0].cross(samples[1]).normalize()
     }
 }
 
-#[derive(Default)]
-pub struct HeightMapAssetLoder;
+/// Latitude in [-PI/2, PI/2] and east longitude in [0, 2PI] of a direction,
+/// with the north pole at +Y and the prime meridian at -Z.
+pub fn geographic_coord(normalized_position: Vec3) -> (f32, f32) {
+    let latitude = normalized_position.y.clamp(-1.0, 1.0).asin();
+    let longitude = normalized_position.x.atan2(normalized_position.z) + PI;
+    (latitude, longitude)
+}
+
+/// Direction for the given latitude and east longitude, inverse of [`geographic_coord`].
+pub fn geographic_direction(latitude: f32, longitude: f32) -> Vec3 {
+    Vec3::new(
+        latitude.cos() * (longitude - PI).sin(),
+        latitude.sin(),
+        latitude.cos() * (longitude - PI).cos(),
+    )
+}
+
+/// Scene directions of the body-fixed axes, x through the prime meridian and z
+/// through the north pole, as the columns of a rotation from the body to the scene.
+pub fn body_axes() -> Mat3 {
+    Mat3::from_cols(
+        geographic_direction(0.0, 0.0),
+        geographic_direction(0.0, FRAC_PI_2),
+        Vec3::Y,
+    )
+}
+
+pub struct HeightMapAssetLoder {
+    pds3: Pds3HeightMapLoader,
+    errors: HeightMapErrors,
+}
+
+impl FromWorld for HeightMapAssetLoder {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            pds3: Pds3HeightMapLoader::from_world(world),
+            errors: HeightMapErrors::shared(world),
+        }
+    }
+}
+
+/// Reads a raw MEGDR image: big-endian 16-bit samples on a global grid twice
+/// as wide as high.
+fn read_megdr(bytes: &[u8]) -> anyhow::Result<HeightMap> {
+    if bytes.is_empty() {
+        bail!("Empty MEGDR image");
+    }
+    // Twice as wide as high, with 2 bytes a sample, the grid takes width² bytes.
+    let width = (bytes.len() as f64).sqrt() as usize;
+    if width * width != bytes.len() || !width.is_multiple_of(2) {
+        bail!(
+            "{} bytes do not make a MEGDR grid of 16-bit samples twice as wide as high",
+            bytes.len()
+        );
+    }
+    let height = width / 2;
+    let data = Samples::from_bytes(bytes, width * height, SampleType::I16, ByteOrder::BigEndian)?;
+    HeightMap::new(width, height, data, None, Projection::global(width))
+}
 
 impl AssetLoader for HeightMapAssetLoder {
     fn load<'a>(
@@ -133,19 +670,16 @@ impl AssetLoader for HeightMapAssetLoder {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let width = (bytes.len() as f64).sqrt() as usize;
-            let height = width / 2;
-            let data = bytes
-                .chunks(2)
-                .map(|chunk| ((chunk[0] as i16) << 8) + chunk[1] as i16)
-                .collect();
-            info!("Loaded {} x {} height map", width, height);
-            let height_map_asset = HeightMap {
-                width,
-                height,
-                data,
+            // Images of PDS3 products may start with their label, like the
+            // HiRISE DTMs.
+            let height_map = if bytes.starts_with(b"PDS_VERSION_ID") {
+                self.pds3.read_height_map(bytes, load_context).await
+            } else {
+                read_megdr(bytes)
             };
-            load_context.set_default_asset(LoadedAsset::new(height_map_asset));
+            let height_map = self.errors.record(load_context.path(), height_map)?;
+            height_map.log_loaded(load_context.path());
+            load_context.set_default_asset(LoadedAsset::new(height_map));
             Ok(())
         })
     }
@@ -154,3 +688,123 @@ impl AssetLoader for HeightMapAssetLoder {
         &["img"]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MEGDR image of big-endian samples, counting from 0 at the north-west corner.
+    fn megdr(width: usize) -> Vec<u8> {
+        (0..width * width / 2)
+            .flat_map(|sample| (sample as i16).to_be_bytes())
+            .collect()
+    }
+
+    /// Direction of the center of a pixel of a global grid.
+    fn pixel_direction(map_width: usize, column: usize, row: usize) -> Vec3 {
+        let size = 360.0 / map_width as f32;
+        let latitude = 90.0 - (row as f32 + 0.5) * size;
+        let longitude = (column as f32 + 0.5) * size;
+        geographic_direction(latitude.to_radians(), longitude.to_radians())
+    }
+
+    #[test]
+    fn reads_megdr_images() {
+        let map = read_megdr(&megdr(8)).unwrap();
+        assert_eq!((map.width, map.height), (8, 4));
+        assert_eq!(map.sample(pixel_direction(8, 0, 0), 0.0), Some(0.0));
+        assert_eq!(map.sample(pixel_direction(8, 3, 0), 0.0), Some(3.0));
+        assert_eq!(map.sample(pixel_direction(8, 2, 3), 0.0), Some(26.0));
+    }
+
+    #[test]
+    fn rejects_truncated_megdr_images() {
+        let mut bytes = megdr(8);
+        bytes.pop();
+        assert!(read_megdr(&bytes).is_err());
+        bytes.truncate(32);
+        assert!(read_megdr(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_megdr_images_not_twice_as_wide_as_high() {
+        // A square grid of 6 x 6 samples.
+        assert!(read_megdr(&[0; 72]).is_err());
+        // 9 bytes would make a grid 3 samples wide and 1.5 high.
+        assert!(read_megdr(&[0; 9]).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        assert!(read_megdr(&[]).is_err());
+        let empty = HeightMap::new(0, 0, Samples::I16(vec![]), None, Projection::global(0));
+        assert!(empty.is_err());
+    }
+
+    #[test]
+    fn rejects_missing_samples() {
+        let map = HeightMap::new(4, 2, Samples::I16(vec![0; 7]), None, Projection::global(4));
+        assert!(map.is_err());
+    }
+
+    #[test]
+    fn decodes_samples_in_both_byte_orders() {
+        let bytes = [0x01, 0x02, 0x03, 0x04];
+        let samples = Samples::from_bytes(&bytes, 2, SampleType::U16, ByteOrder::BigEndian);
+        assert_eq!(samples.unwrap().get(1), 0x0304 as f32);
+        let samples = Samples::from_bytes(&bytes, 2, SampleType::I16, ByteOrder::LittleEndian);
+        assert_eq!(samples.unwrap().get(0), 0x0201 as f32);
+        let samples = Samples::from_bytes(&bytes, 1, SampleType::I32, ByteOrder::LittleEndian);
+        assert_eq!(samples.unwrap().get(0), 0x0403_0201 as f32);
+        let bytes = 1.5_f32.to_be_bytes();
+        let samples = Samples::from_bytes(&bytes, 1, SampleType::F32, ByteOrder::BigEndian);
+        assert_eq!(samples.unwrap().get(0), 1.5);
+    }
+
+    #[test]
+    fn rejects_truncated_samples() {
+        let samples = Samples::from_bytes(&[0; 7], 2, SampleType::F32, ByteOrder::BigEndian);
+        assert!(samples.is_err());
+    }
+
+    #[test]
+    fn leaves_holes_to_the_maps_below() {
+        let global = HeightMap::new(4, 2, Samples::I16(vec![1; 8]), None, Projection::global(4));
+        let mut samples = vec![2.0; 8];
+        samples[0] = -9999.0;
+        samples[1] = f32::NAN;
+        let tile = HeightMap::new(
+            4,
+            2,
+            Samples::F32(samples),
+            Some(-9999.0),
+            Projection::global(4),
+        );
+        let (global, tile) = (global.unwrap(), tile.unwrap());
+        assert_eq!(tile.sample(pixel_direction(4, 0, 0), 0.0), None);
+        assert_eq!(tile.sample(pixel_direction(4, 1, 0), 0.0), None);
+
+        let tiles = HeightMapTiles::new(vec![&global, &tile]);
+        assert_eq!(tiles.get_height_at(pixel_direction(4, 0, 0), 0.0), 1.0);
+        assert_eq!(tiles.get_height_at(pixel_direction(4, 2, 0), 0.0), 2.0);
+    }
+
+    #[test]
+    fn leaves_holes_out_of_the_coarser_levels() {
+        let mut samples = vec![10.0; 8 * 4];
+        samples[0] = -9999.0;
+        samples[1] = 30.0;
+        let map = HeightMap::new(
+            8,
+            4,
+            Samples::F32(samples),
+            Some(-9999.0),
+            Projection::global(8),
+        )
+        .unwrap();
+        assert_eq!(map.height_range(), Some((10.0, 30.0)));
+        let footprint = 2.0 * map.resolution();
+        let filtered = map.sample(pixel_direction(8, 0, 0), footprint).unwrap();
+        assert!(filtered > 10.0 && filtered < 30.0);
+    }
+}
@@ -0,0 +1,168 @@
+use super::{
+    altitude_range::AltitudeRange,
+    color_ramp::{ColorRamp, ColorRamps},
+    view::ColorMode,
+};
+use crate::player::Player;
+use bevy::prelude::*;
+
+const SEGMENTS: usize = 64;
+const TICKS: usize = 6;
+const BAR_WIDTH: f32 = 24.0;
+const BAR_HEIGHT: f32 = 320.0;
+const FONT_SIZE: f32 = 16.0;
+
+/// Any node of the altitude legend, shown only in the altitude color mode.
+#[derive(Component)]
+pub struct LegendNode;
+
+#[derive(Component)]
+pub struct LegendSegment(usize);
+
+#[derive(Component)]
+pub struct LegendTick(usize);
+
+pub fn setup_legend(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let hidden = Visibility { is_visible: false };
+
+    commands.spawn_bundle(UiCameraBundle::default());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(16.0),
+                    top: Val::Px(16.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            visibility: hidden.clone(),
+            ..default()
+        })
+        .insert(LegendNode)
+        .with_children(|legend| {
+            legend
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(BAR_WIDTH), Val::Px(BAR_HEIGHT)),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        ..default()
+                    },
+                    color: Color::NONE.into(),
+                    visibility: hidden.clone(),
+                    ..default()
+                })
+                .insert(LegendNode)
+                .with_children(|bar| {
+                    for index in 0..SEGMENTS {
+                        bar.spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(
+                                    Val::Percent(100.0),
+                                    Val::Percent(100.0 / SEGMENTS as f32),
+                                ),
+                                ..default()
+                            },
+                            visibility: hidden.clone(),
+                            ..default()
+                        })
+                        .insert(LegendNode)
+                        .insert(LegendSegment(index));
+                    }
+                });
+
+            legend
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Auto, Val::Px(BAR_HEIGHT + FONT_SIZE)),
+                        margin: Rect {
+                            top: Val::Px(-FONT_SIZE / 2.0),
+                            left: Val::Px(8.0),
+                            ..default()
+                        },
+                        flex_direction: FlexDirection::ColumnReverse,
+                        justify_content: JustifyContent::SpaceBetween,
+                        ..default()
+                    },
+                    color: Color::NONE.into(),
+                    visibility: hidden.clone(),
+                    ..default()
+                })
+                .insert(LegendNode)
+                .with_children(|ticks| {
+                    for index in 0..TICKS {
+                        ticks
+                            .spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    "",
+                                    TextStyle {
+                                        font: font.clone(),
+                                        font_size: FONT_SIZE,
+                                        color: Color::WHITE,
+                                    },
+                                    default(),
+                                ),
+                                visibility: hidden.clone(),
+                                ..default()
+                            })
+                            .insert(LegendNode)
+                            .insert(LegendTick(index));
+                    }
+                });
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_legend(
+    color_mode: Res<ColorMode>,
+    color_ramps: Res<ColorRamps>,
+    color_ramp_assets: Res<Assets<ColorRamp>>,
+    mut color_ramp_events: EventReader<AssetEvent<ColorRamp>>,
+    ranges: Query<&AltitudeRange>,
+    changed_ranges: Query<(), Changed<AltitudeRange>>,
+    player: Query<&Parent, With<Player>>,
+    changed_focus: Query<(), (With<Player>, Changed<Parent>)>,
+    mut nodes: Query<&mut Visibility, With<LegendNode>>,
+    mut segments: Query<(&LegendSegment, &mut UiColor)>,
+    mut ticks: Query<(&LegendTick, &mut Text)>,
+) {
+    let ramp_loaded = color_ramp_events.iter().count() > 0;
+    if !(color_mode.is_changed()
+        || color_ramps.is_changed()
+        || ramp_loaded
+        || !changed_ranges.is_empty()
+        || !changed_focus.is_empty())
+    {
+        return;
+    }
+
+    let is_visible = *color_mode == ColorMode::Altitude;
+    for mut visibility in nodes.iter_mut() {
+        visibility.is_visible = is_visible;
+    }
+
+    let ramp = color_ramps.active_ramp(&color_ramp_assets);
+    for (segment, mut color) in segments.iter_mut() {
+        *color = ramp
+            .sample((segment.0 as f32 + 0.5) / SEGMENTS as f32)
+            .into();
+    }
+
+    // The range of the body the player is focused on.
+    let range = player
+        .get_single()
+        .ok()
+        .and_then(|focus| ranges.get(focus.0).ok())
+        .or_else(|| ranges.iter().next());
+    if let Some(range) = range {
+        for (tick, mut text) in ticks.iter_mut() {
+            let t = tick.0 as f32 / (TICKS - 1) as f32;
+            let altitude = range.min + t * (range.max - range.min);
+            text.sections[0].value = format!("{:.0} m", altitude);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::{prelude::*, utils::HashMap};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+const FONT_SIZE: f32 = 16.0;
+
+/// Errors of the height maps that failed to load, by asset path, shared with
+/// their loaders since the asset server only keeps them in the log.
+#[derive(Clone, Default)]
+pub struct HeightMapErrors(Arc<Mutex<HashMap<PathBuf, String>>>);
+
+/// Planet whose height maps failed to load, with the error shown in place of
+/// its mesh.
+#[derive(Component)]
+pub struct FailedPlanet(pub String);
+
+#[derive(Component)]
+pub struct LoadErrorLabel;
+
+impl HeightMapErrors {
+    /// The errors shared by the loaders and the systems.
+    pub fn shared(world: &mut World) -> Self {
+        world.get_resource_or_insert_with(Self::default).clone()
+    }
+
+    /// Passes the result of loading the height map at `path` along, keeping
+    /// its error.
+    pub fn record<T>(&self, path: &Path, result: anyhow::Result<T>) -> anyhow::Result<T> {
+        let mut errors = self.0.lock().unwrap();
+        match &result {
+            Ok(_) => errors.remove(path),
+            Err(err) => errors.insert(path.to_owned(), format!("{:#}", err)),
+        };
+        result
+    }
+
+    pub fn get(&self, path: &Path) -> Option<String> {
+        self.0.lock().unwrap().get(path).cloned()
+    }
+}
+
+pub fn setup_load_errors(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(40.0),
+                    ..default()
+                },
+                max_size: Size::new(Val::Percent(60.0), Val::Undefined),
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: FONT_SIZE,
+                    color: Color::rgb(1.0, 0.4, 0.3),
+                },
+                default(),
+            ),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(LoadErrorLabel);
+}
+
+pub fn update_load_errors(
+    failed_planets: Query<(&FailedPlanet, Option<&Name>)>,
+    mut labels: Query<(&mut Text, &mut Visibility), With<LoadErrorLabel>>,
+) {
+    let message = failed_planets
+        .iter()
+        .map(|(FailedPlanet(error), name)| {
+            let name = name.map_or("the planet", |name| name.as_str());
+            format!("Cannot show {}: {}", name, error)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    for (mut text, mut visibility) in labels.iter_mut() {
+        let is_visible = !message.is_empty();
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+        if text.sections[0].value != message {
+            text.sections[0].value = message.clone();
+        }
+    }
+}
@@ -1,23 +1,73 @@
+pub mod altitude_range;
+pub mod atmosphere;
+mod cache;
+pub mod catalog;
+pub mod color_ramp;
+pub mod craters;
+pub mod datum;
+pub mod debug;
+pub mod detail;
+pub mod ellipsoid;
+pub mod geotiff;
 pub mod graphics;
+pub mod graticule;
 pub mod height_map;
+pub mod legend;
+pub mod load_errors;
+pub mod normal_map;
+pub mod pds;
+pub mod projection;
+pub mod pyramid;
+pub mod shadows;
 pub mod view;
 
+use altitude_range::AltitudeRange;
+use atmosphere::Atmosphere;
 use bevy::{
+    asset::{HandleId, LoadState},
     prelude::*,
     render::mesh::{Indices, PrimitiveTopology},
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use color_ramp::{ColorRamp, ColorRamps};
+use datum::{Datum, DatumRelief};
+use detail::{DetailedRelief, ProceduralDetail};
+use ellipsoid::Ellipsoid;
+use futures_lite::future;
+use graphics::{PlanetMaterial, ATTRIBUTE_ALTITUDE, ATTRIBUTE_SUBDIVISION_LEVEL};
+use height_map::{geographic_coord, HeightMap, HeightMapTiles, Relief, HEIGHT_SCALLING};
+use load_errors::{FailedPlanet, HeightMapErrors};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    f32::consts::{FRAC_PI_2, PI},
+    hash::{Hash, Hasher},
 };
-use graphics::{PlanetMaterial, ATTRIBUTE_ALTITUDE};
-use height_map::HeightMap;
-use std::collections::HashMap;
 use view::ColorMode;
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Planet {
     radius: f32,
     min_altitude: f32,
     max_altitude: f32,
     lod_depth: u32,
-    height_map: Handle<HeightMap>,
+    shape: Shape,
+    /// Surface the heights are measured from, the sphere of `radius` without one.
+    datum: Option<Datum>,
+    /// Vertical exaggeration of the relief.
+    height_scale: f32,
+    albedo: Option<Handle<Image>>,
+    /// Color of the real color mode without an albedo map.
+    color: Color,
+    detail: ProceduralDetail,
+    atmosphere: Option<Atmosphere>,
+}
+
+/// Where the heights of a planet come from.
+#[derive(Clone)]
+pub enum Shape {
+    /// Global height map, then tiles replacing it where they have samples.
+    HeightMaps(Vec<Handle<HeightMap>>),
+    Ellipsoid(Ellipsoid),
 }
 
 #[derive(Component)]
@@ -29,6 +79,28 @@ pub struct PlanetMaterials {
 #[derive(Component)]
 pub struct LoadingPlanet;
 
+/// Mesh and normal map of a loading planet being built away from the main thread.
+#[derive(Component)]
+pub struct BuildingPlanet(Task<BuiltPlanet>);
+
+/// Height maps of a planet, taken out of their assets to build it on another thread.
+struct PlanetHeightMaps(HashMap<HandleId, HeightMap>);
+
+pub struct BuiltPlanet {
+    vertices: PlanetVertices,
+    normal_map: Image,
+}
+
+struct PlanetVertices {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    altitudes: Vec<f32>,
+    uvs: Vec<[f32; 2]>,
+    tangents: Vec<[f32; 4]>,
+    subdivision_levels: Vec<f32>,
+    indices: Vec<u32>,
+}
+
 impl Planet {
     pub fn new(
         radius: f32,
@@ -41,61 +113,342 @@ impl Planet {
             min_altitude,
             max_altitude,
             lod_depth: 11,
-            height_map,
+            shape: Shape::HeightMaps(vec![height_map]),
+            datum: None,
+            height_scale: HEIGHT_SCALLING,
+            albedo: None,
+            color: Color::RED,
+            detail: ProceduralDetail::default(),
+            atmosphere: None,
+        }
+    }
+
+    /// Small body shaped like a triaxial ellipsoid, with the given radii in
+    /// metres along its body-fixed axes.
+    pub fn ellipsoid(radii: Vec3) -> Self {
+        let ellipsoid = Ellipsoid::new(radii);
+        let (min_altitude, max_altitude) = ellipsoid.height_range();
+        Self {
+            radius: ellipsoid.mean_radius(),
+            min_altitude,
+            max_altitude,
+            lod_depth: 11,
+            shape: Shape::Ellipsoid(ellipsoid),
+            datum: None,
+            height_scale: HEIGHT_SCALLING,
+            albedo: None,
+            color: Color::RED,
+            detail: ProceduralDetail::default(),
+            atmosphere: None,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Distance from the center to the highest summit, as drawn.
+    pub fn bounding_radius(&self) -> f32 {
+        let datum_radius = self
+            .datum
+            .as_ref()
+            .map_or(self.radius, |datum| datum.max_radius().max(self.radius));
+        datum_radius + self.max_altitude * self.height_scale
+    }
+
+    /// Distance from the center to the datum ellipsoid in a body-fixed direction.
+    pub fn datum_radius_at(&self, normalized_position: Vec3) -> f32 {
+        self.datum.as_ref().map_or(self.radius, |datum| {
+            datum.ellipsoid_radius_at(normalized_position)
+        })
+    }
+
+    pub fn with_datum(mut self, datum: Datum) -> Self {
+        self.datum = Some(datum);
+        self
+    }
+
+    pub fn with_height_scale(mut self, height_scale: f32) -> Self {
+        self.height_scale = height_scale;
+        self
+    }
+
+    /// Subdivisions of the icosahedron the planet mesh is built from.
+    pub fn with_lod_depth(mut self, lod_depth: u32) -> Self {
+        self.lod_depth = lod_depth;
+        self
+    }
+
+    /// Equirectangular albedo map used by the real color mode, starting at 0°E like the height map.
+    pub fn with_albedo(mut self, albedo: Handle<Image>) -> Self {
+        self.albedo = Some(albedo);
+        self
+    }
+
+    /// Height map replacing the previous ones where it has samples, like the
+    /// polar tiles of a global map.
+    pub fn with_height_map_tile(mut self, tile: Handle<HeightMap>) -> Self {
+        if let Shape::HeightMaps(height_maps) = &mut self.shape {
+            height_maps.push(tile);
+        }
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_procedural_detail(mut self, detail: ProceduralDetail) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    pub fn with_atmosphere(mut self, atmosphere: Atmosphere) -> Self {
+        self.atmosphere = Some(atmosphere);
+        self
+    }
+
+    /// Angle between neighbouring vertices of the mesh, in radians.
+    fn vertex_spacing(&self) -> f32 {
+        let icosahedron_edge = 5.0_f32.sqrt().recip().acos();
+        icosahedron_edge / 2.0_f32.powi(self.lod_depth as i32 - 1)
+    }
+
+    /// Height maps of the shape and of the geoid.
+    fn height_map_handles(&self) -> Vec<&Handle<HeightMap>> {
+        let mut handles = match &self.shape {
+            Shape::HeightMaps(handles) => handles.iter().collect(),
+            Shape::Ellipsoid(_) => vec![],
+        };
+        handles.extend(self.datum.as_ref().and_then(Datum::geoid));
+        handles
+    }
+
+    /// Error of the first height map of the planet that failed to load.
+    fn load_error(&self, asset_server: &AssetServer, errors: &HeightMapErrors) -> Option<String> {
+        let handle = self
+            .height_map_handles()
+            .into_iter()
+            .find(|handle| asset_server.get_load_state(*handle) == LoadState::Failed)?;
+        let path = asset_server.get_handle_path(handle)?;
+        let path = path.path();
+        Some(
+            errors
+                .get(path)
+                .unwrap_or_else(|| format!("Cannot load {:?}", path)),
+        )
+    }
+
+    /// Copies of the height maps of the planet, `None` until they are all loaded.
+    fn height_maps(&self, height_maps: &Assets<HeightMap>) -> Option<PlanetHeightMaps> {
+        self.height_map_handles()
+            .into_iter()
+            .map(|handle| Some((handle.id, height_maps.get(handle)?.clone())))
+            .collect::<Option<_>>()
+            .map(PlanetHeightMaps)
+    }
+
+    /// Hash of everything the mesh and the normal map are built from.
+    fn mesh_key(&self, height_maps: &PlanetHeightMaps) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match &self.shape {
+            Shape::HeightMaps(handles) => {
+                for handle in handles {
+                    height_maps.get(handle).fingerprint().hash(&mut hasher);
+                }
+            }
+            Shape::Ellipsoid(ellipsoid) => format!("{:?}", ellipsoid).hash(&mut hasher),
+        }
+        if let Some(datum) = &self.datum {
+            format!("{:?}", datum).hash(&mut hasher);
+            if let Some(geoid) = datum.geoid() {
+                height_maps.get(geoid).fingerprint().hash(&mut hasher);
+            }
         }
+        (self.radius.to_bits(), self.lod_depth).hash(&mut hasher);
+        self.height_scale.to_bits().hash(&mut hasher);
+        format!("{:?}", self.detail).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Relief of the planet before any procedural detail.
+    fn base_relief<'a>(&'a self, height_maps: &'a PlanetHeightMaps) -> Box<dyn Relief + 'a> {
+        match &self.shape {
+            Shape::HeightMaps(handles) => Box::new(HeightMapTiles::new(
+                handles
+                    .iter()
+                    .map(|handle| height_maps.get(handle))
+                    .collect(),
+            )),
+            Shape::Ellipsoid(ellipsoid) => Box::new(ellipsoid.clone()),
+        }
+    }
+
+    /// Relief placed on the datum of the planet.
+    fn datum_relief<'a>(
+        &'a self,
+        relief: &'a dyn Relief,
+        height_maps: &'a PlanetHeightMaps,
+    ) -> DatumRelief<'a> {
+        let relief = DatumRelief::new(relief, self.radius);
+        match &self.datum {
+            Some(datum) => {
+                relief.with_datum(datum, datum.geoid().map(|geoid| height_maps.get(geoid)))
+            }
+            None => relief,
+        }
+    }
+
+    /// Mesh and normal map of the planet, from the cache entries of the
+    /// `body` when one of them holds them.
+    fn build(&self, height_maps: &PlanetHeightMaps, body: &str) -> BuiltPlanet {
+        let key = self.mesh_key(height_maps);
+        if let Some(built) = cache::load_planet(body, key) {
+            return built;
+        }
+
+        let base = self.base_relief(height_maps);
+        let detailed =
+            DetailedRelief::new(base.as_ref(), &self.detail, self.radius, self.height_scale);
+        let relief = self.datum_relief(&detailed, height_maps);
+        let built = BuiltPlanet {
+            normal_map: normal_map::bake_normal_map(&relief, self.radius, self.vertex_spacing()),
+            vertices: build_vertices(self, &relief),
+        };
+        cache::save_planet(body, key, &built);
+        built
     }
 }
 
-pub fn planet_added_system(mut commands: Commands, planets: Query<Entity, Added<Planet>>) {
-    for entity in planets.iter() {
-        commands.entity(entity).insert(LoadingPlanet);
+impl PlanetHeightMaps {
+    /// A height map of the planet, all of them being taken together.
+    fn get(&self, handle: &Handle<HeightMap>) -> &HeightMap {
+        &self.0[&handle.id]
     }
 }
 
-pub fn planet_loading_system(
+pub fn planet_added_system(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut standard_materials: ResMut<Assets<StandardMaterial>>,
-    mut custom_materials: ResMut<Assets<PlanetMaterial>>,
-    height_maps: Res<Assets<HeightMap>>,
-    color_mode: Res<ColorMode>,
-    planets: Query<(Entity, &Planet), With<LoadingPlanet>>,
+    planets: Query<(Entity, &Planet), Added<Planet>>,
 ) {
     for (entity, planet) in planets.iter() {
-        if let Some(height_map) = height_maps.get(planet.height_map.clone()) {
-            let materials = build_materials(
-                &planet,
-                &color_mode,
-                &mut standard_materials,
-                &mut custom_materials,
-            );
-
-            build_planet(
-                &mut commands,
-                &mut meshes,
-                &materials,
-                entity,
-                planet,
-                &color_mode,
-                height_map,
-            );
+        commands
+            .entity(entity)
+            .insert(LoadingPlanet)
+            .insert(AltitudeRange::from_planet(planet));
+    }
+}
 
+/// Starts building the loading planets whose height maps are loaded.
+#[allow(clippy::type_complexity)]
+pub fn planet_loading_system(
+    mut commands: Commands,
+    height_maps: Res<Assets<HeightMap>>,
+    asset_server: Res<AssetServer>,
+    height_map_errors: Res<HeightMapErrors>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    planets: Query<
+        (Entity, &Planet, Option<&Name>),
+        (With<LoadingPlanet>, Without<BuildingPlanet>),
+    >,
+) {
+    for (entity, planet, name) in planets.iter() {
+        if let Some(error) = planet.load_error(&asset_server, &height_map_errors) {
+            error!("Cannot build planet {:?}: {}", name, error);
             commands
                 .entity(entity)
                 .remove::<LoadingPlanet>()
-                .insert(materials);
+                .insert(FailedPlanet(error));
+            continue;
+        }
+        if let Some(height_maps) = planet.height_maps(&height_maps) {
+            info!("Building planet {:?}", name);
+            let planet = planet.clone();
+            let body = name.map_or("planet", Name::as_str).to_owned();
+            let task = thread_pool.spawn(async move { planet.build(&height_maps, &body) });
+            commands.entity(entity).insert(BuildingPlanet(task));
         }
     }
 }
 
+/// Gives the planets built on another thread their mesh and materials.
+#[allow(clippy::too_many_arguments)]
+pub fn planet_built_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut standard_materials: ResMut<Assets<StandardMaterial>>,
+    mut custom_materials: ResMut<Assets<PlanetMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    color_ramp_assets: Res<Assets<ColorRamp>>,
+    color_mode: Res<ColorMode>,
+    color_ramps: Res<ColorRamps>,
+    mut planets: Query<(
+        Entity,
+        &Planet,
+        &AltitudeRange,
+        &mut BuildingPlanet,
+        Option<&Name>,
+    )>,
+) {
+    for (entity, planet, altitude_range, mut task, name) in planets.iter_mut() {
+        let built = match future::block_on(future::poll_once(&mut task.0)) {
+            Some(built) => built,
+            None => continue,
+        };
+        let albedo = planet
+            .albedo
+            .clone()
+            .filter(|albedo| images.get(albedo).is_some());
+        let materials = build_materials(
+            altitude_range,
+            planet.height_scale,
+            albedo,
+            planet.color,
+            images.add(built.normal_map),
+            &color_mode,
+            color_ramps.active_texture(&color_ramp_assets),
+            &mut standard_materials,
+            &mut custom_materials,
+        );
+        build_planet(
+            &mut commands,
+            &mut meshes,
+            &materials,
+            entity,
+            &color_mode,
+            built.vertices,
+        );
+        info!("Built planet {:?}", name);
+
+        commands
+            .entity(entity)
+            .remove::<LoadingPlanet>()
+            .remove::<BuildingPlanet>()
+            .insert(materials);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn build_materials(
-    planet: &Planet,
+    altitude_range: &AltitudeRange,
+    height_scale: f32,
+    albedo: Option<Handle<Image>>,
+    color: Color,
+    normal_map: Handle<Image>,
     color_mode: &ColorMode,
+    color_ramp: Handle<Image>,
     standard_materials: &mut Assets<StandardMaterial>,
     custom_materials: &mut Assets<PlanetMaterial>,
 ) -> PlanetMaterials {
     let standard = standard_materials.add(StandardMaterial {
-        base_color: Color::RED,
+        base_color: if albedo.is_some() {
+            Color::WHITE
+        } else {
+            color
+        },
+        base_color_texture: albedo,
+        normal_map_texture: Some(normal_map.clone()),
         metallic: 0.0,
         perceptual_roughness: 0.5,
         reflectance: 0.1,
@@ -103,9 +456,11 @@ fn build_materials(
     });
 
     let custom = custom_materials.add(PlanetMaterial {
-        min_altitude: planet.min_altitude * height_map::HEIGHT_SCALLING,
-        max_altitude: planet.max_altitude * height_map::HEIGHT_SCALLING,
+        min_altitude: altitude_range.min * height_scale,
+        max_altitude: altitude_range.max * height_scale,
         color_mode: color_mode.clone() as u32,
+        color_ramp,
+        normal_map: Some(normal_map),
     });
 
     PlanetMaterials { standard, custom }
@@ -116,25 +471,21 @@ fn build_planet(
     meshes: &mut Assets<Mesh>,
     materials: &PlanetMaterials,
     entity: Entity,
-    planet: &Planet,
     color_mode: &ColorMode,
-    height_map: &HeightMap,
+    vertices: PlanetVertices,
 ) {
-    info!("Building planet!!!");
-
-    let (vertices, normals, altitudes, indices) = build_vertices(planet, height_map);
-
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; vertices.len()]);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_attribute(ATTRIBUTE_ALTITUDE, altitudes);
-    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vertices.uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, vertices.tangents);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices.positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vertices.normals);
+    mesh.insert_attribute(ATTRIBUTE_ALTITUDE, vertices.altitudes);
+    mesh.insert_attribute(ATTRIBUTE_SUBDIVISION_LEVEL, vertices.subdivision_levels);
+    mesh.set_indices(Some(Indices::U32(vertices.indices)));
 
     commands
         .entity(entity)
         .insert(meshes.add(mesh))
-        .insert_bundle(TransformBundle::default())
         .insert(Visibility::default())
         .insert(ComputedVisibility::default());
 
@@ -143,18 +494,14 @@ fn build_planet(
     } else {
         commands.entity(entity).insert(materials.standard.clone());
     }
-
-    info!("Building planet: done");
 }
 
-fn build_vertices(
-    planet: &Planet,
-    height_map: &HeightMap,
-) -> (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<f32>, Vec<u32>) {
+fn build_vertices(planet: &Planet, relief: &DatumRelief) -> PlanetVertices {
     let mut vertices = initial_vertices();
+    let mut levels = vec![0; vertices.len()];
     let mut triangles = initial_triangles();
 
-    for _ in 1..planet.lod_depth {
+    for level in 1..planet.lod_depth {
         let mut mid_vertices = HashMap::<(u32, u32), u32>::new();
         let mut new_triangles = vec![];
 
@@ -170,20 +517,110 @@ fn build_vertices(
         }
 
         triangles = new_triangles;
+        levels.resize(vertices.len(), level);
     }
 
+    let uvs = split_uv_seam(&mut vertices, &mut levels, &mut triangles);
+
     let indices = triangles.into_iter().flatten().collect();
     let mut normals = vec![];
     let mut vertices_with_height = vec![];
     let mut altitudes = vec![];
-    for vertice in vertices {
-        let (height, normal) = height_map.fetch_relief_at(vertice, planet.radius);
+    let mut tangents = vec![];
+    let subdivision_levels = levels
+        .into_iter()
+        .map(|level| level as f32 / (planet.lod_depth - 1).max(1) as f32)
+        .collect();
+    let footprint = planet.vertex_spacing();
+    for (vertice, uv) in vertices.into_iter().zip(uvs.iter()) {
+        let (height, normal) = relief.fetch_relief_at(vertice, planet.radius, footprint);
         normals.push(normal.into());
         vertices_with_height.push((vertice * (planet.radius + height)).into());
-        altitudes.push(height);
+        altitudes.push(height - relief.datum_height_at(vertice));
+        tangents.push(spherical_tangent(uv, normal));
+    }
+
+    PlanetVertices {
+        positions: vertices_with_height,
+        normals,
+        altitudes,
+        uvs,
+        tangents,
+        subdivision_levels,
+        indices,
     }
+}
+
+/// Computes the equirectangular UVs of the vertices, duplicating the ones on
+/// triangles crossing the antimeridian so their U does not wrap around, and
+/// giving each triangle touching a pole its own pole vertex. The subdivision
+/// levels of the duplicated vertices are copied along.
+fn split_uv_seam(
+    vertices: &mut Vec<Vec3>,
+    levels: &mut Vec<u32>,
+    triangles: &mut [[u32; 3]],
+) -> Vec<[f32; 2]> {
+    let mut uvs: Vec<[f32; 2]> = vertices.iter().map(|v| spherical_uv(*v)).collect();
+    let mut wrapped_vertices = HashMap::<u32, u32>::new();
+    let is_pole = |vertice: &Vec3| vertice.y.abs() > 1.0 - 1e-6;
+
+    for triangle in triangles.iter_mut() {
+        let corners: Vec<usize> = (0..3)
+            .filter(|&corner| !is_pole(&vertices[triangle[corner] as usize]))
+            .collect();
+
+        let us = corners
+            .iter()
+            .map(|&corner| uvs[triangle[corner] as usize][0]);
+        let (min_u, max_u) = us.fold((f32::MAX, f32::MIN), |(min, max), u| {
+            (min.min(u), max.max(u))
+        });
+        if max_u - min_u > 0.5 {
+            for &corner in corners.iter() {
+                let index = triangle[corner];
+                let [u, v] = uvs[index as usize];
+                if u < 0.5 {
+                    triangle[corner] = *wrapped_vertices.entry(index).or_insert_with(|| {
+                        vertices.push(vertices[index as usize]);
+                        levels.push(levels[index as usize]);
+                        uvs.push([u + 1.0, v]);
+                        vertices.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        if corners.len() < 3 {
+            let mean_u = corners
+                .iter()
+                .map(|&corner| uvs[triangle[corner] as usize][0])
+                .sum::<f32>()
+                / corners.len() as f32;
+            for corner in (0..3).filter(|corner| !corners.contains(corner)) {
+                let index = triangle[corner] as usize;
+                vertices.push(vertices[index]);
+                levels.push(levels[index]);
+                uvs.push([mean_u, uvs[index][1]]);
+                triangle[corner] = vertices.len() as u32 - 1;
+            }
+        }
+    }
+
+    uvs
+}
+
+fn spherical_uv(normalized_position: Vec3) -> [f32; 2] {
+    let (latitude, longitude) = geographic_coord(normalized_position);
+    [longitude / (2.0 * PI), (FRAC_PI_2 - latitude) / PI]
+}
 
-    (vertices_with_height, normals, altitudes, indices)
+/// Tangent pointing east, along increasing U, made orthogonal to the terrain
+/// normal. The bitangent, `normal x tangent`, points north.
+fn spherical_tangent(uv: &[f32; 2], normal: Vec3) -> [f32; 4] {
+    let longitude = uv[0] * 2.0 * PI - PI;
+    let east = Vec3::new(longitude.cos(), 0.0, -longitude.sin());
+    let tangent = (east - normal * normal.dot(east)).normalize();
+    [tangent.x, tangent.y, tangent.z, 1.0]
 }
 
 fn get_middle_vertex(
@@ -0,0 +1,92 @@
+use super::{
+    height_map::{geographic_direction, Relief},
+    spherical_tangent,
+};
+use bevy::{
+    prelude::*,
+    render::render_resource::{
+        AddressMode, Extent3d, FilterMode, SamplerDescriptor, TextureDimension, TextureFormat,
+    },
+};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Largest texture width accepted by most GPUs.
+const MAX_WIDTH: usize = 8192;
+
+/// Bakes an equirectangular tangent-space normal map from the relief, its
+/// pixels no smaller than the resolution of the relief.
+///
+/// The normals are expressed in the tangent frame of the mesh, whose normal is
+/// the coarse one computed by [`Relief::fetch_relief_at`] at the spacing of
+/// its vertices, so lighting only gains the detail the mesh is missing.
+pub fn bake_normal_map(relief: &impl Relief, radius: f32, vertex_spacing: f32) -> Image {
+    let width = ((2.0 * PI / relief.resolution()).floor() as usize).clamp(2, MAX_WIDTH);
+    let height = width / 2;
+    info!("Baking {} x {} normal map", width, height);
+
+    let delta_longitude = 2.0 * PI / width as f32;
+    let delta_latitude = PI / height as f32;
+    let mut data = Vec::with_capacity(width * height * 4);
+
+    for row in 0..height {
+        let v = (row as f32 + 0.5) / height as f32;
+        let latitude = FRAC_PI_2 - v * PI;
+        let north_distance = 2.0 * delta_latitude * radius;
+        let east_distance = (2.0 * delta_longitude * radius * latitude.cos()).max(1.0);
+
+        for col in 0..width {
+            let u = (col as f32 + 0.5) / width as f32;
+            let longitude = u * 2.0 * PI;
+            let height_at = |latitude: f32, longitude: f32| {
+                relief.get_height_at(geographic_direction(latitude, longitude), delta_latitude)
+            };
+
+            let direction = geographic_direction(latitude, longitude);
+            let east = Vec3::new(-longitude.cos(), 0.0, longitude.sin());
+            let north = direction.cross(east);
+            let east_slope = (height_at(latitude, longitude + delta_longitude)
+                - height_at(latitude, longitude - delta_longitude))
+                / east_distance;
+            let north_slope = (height_at(latitude + delta_latitude, longitude)
+                - height_at(latitude - delta_latitude, longitude))
+                / north_distance;
+            let fine_normal = (direction - east * east_slope - north * north_slope).normalize();
+
+            let (_, coarse_normal) = relief.fetch_relief_at(direction, radius, vertex_spacing);
+            let tangent = Vec4::from(spherical_tangent(&[u, v], coarse_normal)).truncate();
+            let bitangent = coarse_normal.cross(tangent);
+            let tangent_space = Vec3::new(
+                fine_normal.dot(tangent),
+                fine_normal.dot(bitangent),
+                fine_normal.dot(coarse_normal),
+            );
+
+            let encoded = (tangent_space * 0.5 + 0.5) * 255.0;
+            data.extend([encoded.x as u8, encoded.y as u8, encoded.z as u8, 255]);
+        }
+    }
+
+    image(width, height, data)
+}
+
+/// Normal map of `width` x `height` pixels, four bytes each.
+pub fn image(width: usize, height: usize, data: Vec<u8>) -> Image {
+    let mut image = Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    );
+    image.sampler_descriptor = SamplerDescriptor {
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        ..default()
+    };
+    image
+}
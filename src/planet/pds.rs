@@ -0,0 +1,453 @@
+use super::{
+    height_map::{ByteOrder, HeightMap, MappedSamples, SampleType, Samples},
+    load_errors::HeightMapErrors,
+    projection::Projection,
+};
+use anyhow::{anyhow, bail, Context};
+use bevy::{
+    asset::{AssetLoader, AssetServerSettings, FileAssetIo, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Keyword values of a PDS3 label, keyed by the innermost object they are in,
+/// as in `IMAGE/LINES`. Keywords outside of any object have no prefix.
+struct Label {
+    values: HashMap<String, String>,
+}
+
+impl Label {
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut values = HashMap::new();
+        let mut objects: Vec<String> = Vec::new();
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            let line = strip_comment(line).trim();
+            if line == "END" {
+                break;
+            }
+            let (keyword, value) = match line.split_once('=') {
+                Some((keyword, value)) => (keyword.trim(), value.trim().to_string()),
+                None => continue,
+            };
+            // Quoted texts and lists may go on over the next lines.
+            let mut value = value;
+            while !is_complete(&value) {
+                let next = lines
+                    .next()
+                    .ok_or_else(|| anyhow!("Unterminated value of {}", keyword))?;
+                value.push(' ');
+                value.push_str(strip_comment(next).trim());
+            }
+
+            match keyword {
+                "OBJECT" => objects.push(value),
+                "END_OBJECT" => {
+                    objects.pop();
+                }
+                _ => {
+                    let key = match objects.last() {
+                        Some(object) => format!("{}/{}", object, keyword),
+                        None => keyword.to_string(),
+                    };
+                    values.entry(key).or_insert(value);
+                }
+            }
+        }
+        if !values.contains_key("PDS_VERSION_ID") {
+            bail!("Not a PDS3 label, PDS_VERSION_ID is missing");
+        }
+        Ok(Self { values })
+    }
+
+    fn text(&self, key: &str) -> anyhow::Result<&str> {
+        let value = self
+            .values
+            .get(key)
+            .ok_or_else(|| anyhow!("Missing {} in the label", key))?;
+        Ok(value.trim_matches('"'))
+    }
+
+    /// Number, with its unit if it has one.
+    fn quantity(&self, key: &str) -> anyhow::Result<(f32, Option<&str>)> {
+        let text = self.text(key)?;
+        let (number, unit) = match text.split_once('<') {
+            Some((number, unit)) => (number, Some(unit.trim_end_matches('>').trim())),
+            None => (text, None),
+        };
+        let number = number
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid {} in the label: {}", key, text))?;
+        Ok((number, unit))
+    }
+
+    fn number(&self, key: &str) -> anyhow::Result<f32> {
+        Ok(self.quantity(key)?.0)
+    }
+
+    fn count(&self, key: &str) -> anyhow::Result<usize> {
+        let text = self.text(key)?;
+        text.parse()
+            .with_context(|| format!("Invalid {} in the label: {}", key, text))
+    }
+
+    /// Length in metres, from kilometres unless the unit says otherwise.
+    fn length(&self, key: &str) -> anyhow::Result<f32> {
+        let (number, unit) = self.quantity(key)?;
+        Ok(match unit {
+            Some(unit) if unit.starts_with('M') => number,
+            _ => number * 1000.0,
+        })
+    }
+
+    fn sample_type(&self) -> anyhow::Result<(SampleType, ByteOrder)> {
+        use ByteOrder::*;
+        let bits = self.number("IMAGE/SAMPLE_BITS")?;
+        let sample_type = self.text("IMAGE/SAMPLE_TYPE")?;
+        let (sample_type, byte_order) = match (sample_type, bits as u32) {
+            ("MSB_INTEGER" | "INTEGER" | "SUN_INTEGER" | "MAC_INTEGER", 16) => {
+                (SampleType::I16, BigEndian)
+            }
+            ("LSB_INTEGER" | "PC_INTEGER" | "VAX_INTEGER", 16) => (SampleType::I16, LittleEndian),
+            (
+                "MSB_UNSIGNED_INTEGER"
+                | "UNSIGNED_INTEGER"
+                | "SUN_UNSIGNED_INTEGER"
+                | "MAC_UNSIGNED_INTEGER",
+                16,
+            ) => (SampleType::U16, BigEndian),
+            ("LSB_UNSIGNED_INTEGER" | "PC_UNSIGNED_INTEGER" | "VAX_UNSIGNED_INTEGER", 16) => {
+                (SampleType::U16, LittleEndian)
+            }
+            ("MSB_INTEGER" | "INTEGER" | "SUN_INTEGER" | "MAC_INTEGER", 32) => {
+                (SampleType::I32, BigEndian)
+            }
+            ("LSB_INTEGER" | "PC_INTEGER" | "VAX_INTEGER", 32) => (SampleType::I32, LittleEndian),
+            ("IEEE_REAL" | "REAL" | "FLOAT" | "SUN_REAL" | "MAC_REAL", 32) => {
+                (SampleType::F32, BigEndian)
+            }
+            ("PC_REAL", 32) => (SampleType::F32, LittleEndian),
+            _ => bail!("Unsupported {}-bit {} samples", bits, sample_type),
+        };
+        Ok((sample_type, byte_order))
+    }
+
+    /// Sample value of a keyword, either a number or the bits of the sample
+    /// in hexadecimal, as in `16#FF7FFFFB#`.
+    fn sample_value(&self, key: &str, sample_type: SampleType) -> anyhow::Result<f32> {
+        let text = self.text(key)?;
+        let hexadecimal = match text
+            .strip_prefix("16#")
+            .and_then(|text| text.strip_suffix('#'))
+        {
+            Some(hexadecimal) => hexadecimal,
+            None => return self.number(key),
+        };
+        let bits = u32::from_str_radix(hexadecimal, 16)
+            .with_context(|| format!("Invalid {} in the label: {}", key, text))?;
+        Ok(match sample_type {
+            SampleType::I16 => bits as u16 as i16 as f32,
+            SampleType::U16 => bits as u16 as f32,
+            SampleType::I32 => bits as i32 as f32,
+            SampleType::F32 => f32::from_bits(bits),
+        })
+    }
+
+    fn projection(&self) -> anyhow::Result<Projection> {
+        let key = |keyword| format!("IMAGE_MAP_PROJECTION/{}", keyword);
+        let projection_type = self.text(&key("MAP_PROJECTION_TYPE"))?;
+        if self
+            .text(&key("POSITIVE_LONGITUDE_DIRECTION"))
+            .unwrap_or("EAST")
+            != "EAST"
+        {
+            bail!("Only east longitudes are supported");
+        }
+        let line_offset = self.number(&key("LINE_PROJECTION_OFFSET"))?;
+        let sample_offset = self.number(&key("SAMPLE_PROJECTION_OFFSET"))?;
+        let center_longitude = self.number(&key("CENTER_LONGITUDE"))?;
+
+        match projection_type {
+            "SIMPLE CYLINDRICAL" => Ok(Projection::Equirectangular {
+                pixels_per_degree: self.number(&key("MAP_RESOLUTION"))?,
+                line_offset,
+                sample_offset,
+                center_longitude,
+                standard_parallel: 0.0,
+            }),
+            "EQUIRECTANGULAR" => Ok(Projection::Equirectangular {
+                pixels_per_degree: self.number(&key("MAP_RESOLUTION"))?,
+                line_offset,
+                sample_offset,
+                center_longitude,
+                standard_parallel: self.number(&key("CENTER_LATITUDE"))?,
+            }),
+            "POLAR STEREOGRAPHIC" => Ok(Projection::PolarStereographic {
+                north: self.number(&key("CENTER_LATITUDE"))? > 0.0,
+                scale: self.length(&key("MAP_SCALE"))?,
+                line_offset,
+                sample_offset,
+                center_longitude,
+                radius: self.length(&key("A_AXIS_RADIUS"))?,
+            }),
+            _ => bail!("Unsupported map projection {}", projection_type),
+        }
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find("/*") {
+        Some(start) => &line[..start],
+        None => line,
+    }
+}
+
+fn is_complete(value: &str) -> bool {
+    let quotes = value.matches('"').count();
+    let opened = value.matches(['(', '{']).count();
+    let closed = value.matches([')', '}']).count();
+    quotes.is_multiple_of(2) && opened <= closed
+}
+
+/// Height maps described by a PDS3 label, like the LOLA LDEM and MOLA MEGDR
+/// products: 16-bit or 32-bit samples on a simple cylindrical,
+/// equirectangular or polar stereographic grid.
+///
+/// Images in their own files are mapped in memory rather than read, so that
+/// the largest ones only take the memory of the parts being sampled.
+pub struct Pds3HeightMapLoader {
+    /// Folder of the assets on the disk.
+    asset_folder: PathBuf,
+    errors: HeightMapErrors,
+}
+
+impl FromWorld for Pds3HeightMapLoader {
+    fn from_world(world: &mut World) -> Self {
+        let asset_folder = world
+            .get_resource::<AssetServerSettings>()
+            .map(|settings| settings.asset_folder.clone())
+            .unwrap_or_else(|| "assets".to_string());
+        Self {
+            asset_folder: FileAssetIo::get_root_path().join(asset_folder),
+            errors: HeightMapErrors::shared(world),
+        }
+    }
+}
+
+impl Pds3HeightMapLoader {
+    /// Reads a height map described by a PDS3 label, the label file or an
+    /// image starting with its label.
+    pub async fn read_height_map(
+        &self,
+        bytes: &[u8],
+        load_context: &LoadContext<'_>,
+    ) -> anyhow::Result<HeightMap> {
+        let label = Label::parse(&String::from_utf8_lossy(bytes))?;
+        let width = label.count("IMAGE/LINE_SAMPLES")?;
+        let height = label.count("IMAGE/LINES")?;
+        let (sample_type, byte_order) = label.sample_type()?;
+        let scale = label.number("IMAGE/SCALING_FACTOR").unwrap_or(1.0);
+        let mut offset = label.number("IMAGE/OFFSET").unwrap_or(0.0);
+        let projection = label.projection()?;
+
+        // LOLA samples are radii, the offset being the reference radius,
+        // MEGDR samples are already heights.
+        let radius = label.length("IMAGE_MAP_PROJECTION/A_AXIS_RADIUS")?;
+        if (offset - radius).abs() < offset.abs() {
+            offset -= radius;
+        }
+
+        let data = self
+            .read_samples(
+                &label,
+                bytes,
+                load_context,
+                width * height,
+                sample_type,
+                byte_order,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Cannot read the {} x {} samples of the image",
+                    width, height
+                )
+            })?;
+        let no_data = label
+            .sample_value("IMAGE/MISSING_CONSTANT", sample_type)
+            .ok();
+        Ok(HeightMap::new(width, height, data, no_data, projection)?.with_scaling(scale, offset))
+    }
+
+    /// Reads the samples of the image a PDS3 label points to, either in the
+    /// label file itself, counted in records, or in a file next to it.
+    async fn read_samples(
+        &self,
+        label: &Label,
+        bytes: &[u8],
+        load_context: &LoadContext<'_>,
+        count: usize,
+        sample_type: SampleType,
+        byte_order: ByteOrder,
+    ) -> anyhow::Result<Samples> {
+        let pointer = label.text("^IMAGE")?;
+        let record = |record: &str| -> anyhow::Result<usize> {
+            // Pointers count records, or bytes when they say so.
+            if let Some(start) = record.trim().strip_suffix("<BYTES>") {
+                let start: usize = start.trim().parse()?;
+                return Ok(start.saturating_sub(1));
+            }
+            let record: usize = record.trim().parse()?;
+            let record_bytes = label.number("RECORD_BYTES")? as usize;
+            Ok(record.saturating_sub(1) * record_bytes)
+        };
+
+        if let Ok(start) = record(pointer) {
+            let image = bytes.get(start..).unwrap_or_default();
+            return Samples::from_bytes(image, count, sample_type, byte_order);
+        }
+        let pointer = pointer.trim_matches(|c| c == '(' || c == ')');
+        let (file, start) = match pointer.split_once(',') {
+            Some((file, start)) => (file, record(start)?),
+            None => (pointer, 0),
+        };
+        let file = file.trim().trim_matches('"');
+
+        // Labels name their images in upper case, archives are often unpacked
+        // in lower case.
+        let directory = load_context
+            .path()
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let mut last_error = None;
+        for name in [file.to_string(), file.to_lowercase()] {
+            let path = directory.join(name);
+            let disk_path = self.asset_folder.join(&path);
+            if disk_path.is_file() {
+                let samples =
+                    MappedSamples::open(&disk_path, start, count, sample_type, byte_order)?;
+                return Ok(Samples::Mapped(samples));
+            }
+            match load_context.read_asset_bytes(path).await {
+                Ok(image) => {
+                    let image = image.get(start..).unwrap_or_default();
+                    return Samples::from_bytes(image, count, sample_type, byte_order);
+                }
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(anyhow!("Cannot read image {}: {:?}", file, last_error))
+    }
+}
+
+impl AssetLoader for Pds3HeightMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let height_map = self.read_height_map(bytes, load_context).await;
+            let height_map = self.errors.record(load_context.path(), height_map)?;
+            height_map.log_loaded(load_context.path());
+            load_context.set_default_asset(LoadedAsset::new(height_map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lbl", "LBL"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABEL: &str = r#"PDS_VERSION_ID = PDS3
+RECORD_TYPE = FIXED_LENGTH
+RECORD_BYTES = 8
+^IMAGE = "LDEM_4.IMG"
+/* The image and its projection. */
+OBJECT = IMAGE
+  LINES = 2
+  LINE_SAMPLES = 4
+  SAMPLE_TYPE = PC_REAL
+  SAMPLE_BITS = 32
+  MISSING_CONSTANT = 16#FF7FFFFB#
+  DESCRIPTION = "Heights above
+    the sphere"
+END_OBJECT = IMAGE
+OBJECT = IMAGE_MAP_PROJECTION
+  MAP_PROJECTION_TYPE = "SIMPLE CYLINDRICAL"
+  A_AXIS_RADIUS = 1737.4 <KM>
+  MAP_RESOLUTION = 1 <PIX/DEG>
+  LINE_PROJECTION_OFFSET = 0.5 <PIXEL>
+  SAMPLE_PROJECTION_OFFSET = -0.5 <PIXEL>
+  CENTER_LONGITUDE = 0.0 <DEG>
+END_OBJECT = IMAGE_MAP_PROJECTION
+END
+"#;
+
+    #[test]
+    fn parses_labels() {
+        let label = Label::parse(LABEL).unwrap();
+        assert_eq!(label.count("IMAGE/LINE_SAMPLES").unwrap(), 4);
+        assert_eq!(
+            label.text("IMAGE/DESCRIPTION").unwrap(),
+            "Heights above the sphere"
+        );
+        assert_eq!(
+            label.length("IMAGE_MAP_PROJECTION/A_AXIS_RADIUS").unwrap(),
+            1737400.0
+        );
+        assert_eq!(
+            label.sample_type().unwrap(),
+            (SampleType::F32, ByteOrder::LittleEndian)
+        );
+        let no_data = label.sample_value("IMAGE/MISSING_CONSTANT", SampleType::F32);
+        assert_eq!(no_data.unwrap(), -3.402_822_7e38);
+        assert!(label.projection().is_ok());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_labels() {
+        assert!(Label::parse("").is_err());
+        assert!(Label::parse("LINES = 2\nLINE_SAMPLES = 4\nEND").is_err());
+        let image = [0x7f, 0x45, 0x3d, 0x00, 0xff, 0x0a, 0x3d, 0x22];
+        assert!(Label::parse(&String::from_utf8_lossy(&image)).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_labels() {
+        let truncated = &LABEL[..LABEL.find("the sphere").unwrap()];
+        assert!(Label::parse(truncated).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_samples() {
+        let label = Label::parse(&LABEL.replace("SAMPLE_BITS = 32", "SAMPLE_BITS = 8")).unwrap();
+        assert!(label.sample_type().is_err());
+        let label = Label::parse(&LABEL.replace("PC_REAL", "VAX_REAL")).unwrap();
+        assert!(label.sample_type().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        let label = Label::parse(&LABEL.replace("LINES = 2", "LINES = 2.5")).unwrap();
+        assert!(label.count("IMAGE/LINES").is_err());
+        let label = Label::parse(&LABEL.replace("LINES = 2", "LINES = -2")).unwrap();
+        assert!(label.count("IMAGE/LINES").is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_projections() {
+        let label = Label::parse(&LABEL.replace("SIMPLE CYLINDRICAL", "MERCATOR")).unwrap();
+        assert!(label.projection().is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    f32::consts::{FRAC_PI_2, FRAC_PI_4},
+    fmt,
+};
+
+/// How the samples of a height map are laid out over the planet, with the
+/// parameters of the PDS map projection labels.
+///
+/// Pixel positions count from 0 at the center of the first pixel.
+#[derive(Debug, Clone)]
+pub enum Projection {
+    /// Grid of latitudes and east longitudes, the longitudes shrunk by the
+    /// cosine of the standard parallel, 0 for a simple cylindrical grid.
+    Equirectangular {
+        /// Along the meridians.
+        pixels_per_degree: f32,
+        /// Position of the equator and of the center longitude, in pixels.
+        line_offset: f32,
+        sample_offset: f32,
+        center_longitude: f32,
+        standard_parallel: f32,
+    },
+    /// Projection of a polar cap on the plane tangent to the pole.
+    PolarStereographic {
+        north: bool,
+        /// Size of a pixel, in metres.
+        scale: f32,
+        /// Position of the pole, in pixels.
+        line_offset: f32,
+        sample_offset: f32,
+        /// Longitude pointing down from the north pole, or up from the south pole.
+        center_longitude: f32,
+        /// Radius of the projection sphere, in metres.
+        radius: f32,
+    },
+}
+
+/// Latitudes and east longitudes covered by a height map, in degrees.
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub south: f32,
+    pub north: f32,
+    pub west: f32,
+    pub east: f32,
+}
+
+impl Projection {
+    /// Global grid of `width` samples around the equator, starting at 90°N and 0°E.
+    pub fn global(width: usize) -> Self {
+        let pixels_per_degree = width as f32 / 360.0;
+        Self::Equirectangular {
+            pixels_per_degree,
+            line_offset: 90.0 * pixels_per_degree - 0.5,
+            sample_offset: -0.5,
+            center_longitude: 0.0,
+            standard_parallel: 0.0,
+        }
+    }
+
+    /// Column and row of the pixel at a latitude and east longitude, in radians.
+    pub fn pixel_at(&self, latitude: f32, longitude: f32) -> (f32, f32) {
+        match *self {
+            Self::Equirectangular {
+                pixels_per_degree,
+                line_offset,
+                sample_offset,
+                center_longitude,
+                standard_parallel,
+            } => {
+                let longitude =
+                    (longitude.to_degrees() - center_longitude + 180.0).rem_euclid(360.0) - 180.0;
+                (
+                    sample_offset
+                        + longitude * pixels_per_degree * standard_parallel.to_radians().cos(),
+                    line_offset - latitude.to_degrees() * pixels_per_degree,
+                )
+            }
+            Self::PolarStereographic {
+                north,
+                scale,
+                line_offset,
+                sample_offset,
+                center_longitude,
+                radius,
+            } => {
+                let (distance, y_sign) = if north {
+                    ((FRAC_PI_4 - latitude / 2.0).tan(), -1.0)
+                } else {
+                    ((FRAC_PI_4 + latitude / 2.0).tan(), 1.0)
+                };
+                let distance = 2.0 * radius * distance;
+                let angle = longitude - center_longitude.to_radians();
+                let x = distance * angle.sin();
+                let y = y_sign * distance * angle.cos();
+                (sample_offset + x / scale, line_offset - y / scale)
+            }
+        }
+    }
+
+    /// Latitude and east longitude, in radians, at a pixel position, inverse of
+    /// [`Projection::pixel_at`].
+    pub fn coordinates_at(&self, column: f32, row: f32) -> (f32, f32) {
+        match *self {
+            Self::Equirectangular {
+                pixels_per_degree,
+                line_offset,
+                sample_offset,
+                center_longitude,
+                standard_parallel,
+            } => {
+                let longitude = center_longitude
+                    + (column - sample_offset)
+                        / (pixels_per_degree * standard_parallel.to_radians().cos());
+                let latitude = (line_offset - row) / pixels_per_degree;
+                (latitude.to_radians(), longitude.to_radians())
+            }
+            Self::PolarStereographic {
+                north,
+                scale,
+                line_offset,
+                sample_offset,
+                center_longitude,
+                radius,
+            } => {
+                let x = (column - sample_offset) * scale;
+                let y = (line_offset - row) * scale;
+                let colatitude = 2.0 * (x.hypot(y) / (2.0 * radius)).atan();
+                let (latitude, angle) = if north {
+                    (FRAC_PI_2 - colatitude, x.atan2(-y))
+                } else {
+                    (colatitude - FRAC_PI_2, x.atan2(y))
+                };
+                (latitude, center_longitude.to_radians() + angle)
+            }
+        }
+    }
+
+    /// Whether a grid `width` samples wide goes all around the planet.
+    pub fn wraps_around(&self, width: usize) -> bool {
+        match *self {
+            Self::Equirectangular {
+                pixels_per_degree,
+                standard_parallel,
+                ..
+            } => {
+                let samples_around =
+                    360.0 * pixels_per_degree * standard_parallel.to_radians().cos();
+                (width as f32 - samples_around).abs() < 0.5
+            }
+            Self::PolarStereographic { .. } => false,
+        }
+    }
+
+    /// Area covered by a grid of `width` x `height` samples.
+    pub fn bounds(&self, width: usize, height: usize) -> Bounds {
+        let (right, bottom) = (width as f32 - 0.5, height as f32 - 0.5);
+        match self {
+            Self::Equirectangular { .. } => {
+                let (north, west) = self.coordinates_at(-0.5, -0.5);
+                let (south, east) = self.coordinates_at(right, bottom);
+                let bounds = Bounds {
+                    south: south.to_degrees().max(-90.0),
+                    north: north.to_degrees().min(90.0),
+                    west: west.to_degrees(),
+                    east: east.to_degrees(),
+                };
+                if self.wraps_around(width) {
+                    Bounds {
+                        west: 0.0,
+                        east: 360.0,
+                        ..bounds
+                    }
+                } else {
+                    bounds
+                }
+            }
+            Self::PolarStereographic { north, .. } => {
+                // The latitude farthest from the pole lies on the edges.
+                let edges = (0..=width).flat_map(|column| {
+                    let column = column as f32 - 0.5;
+                    [(column, -0.5), (column, bottom)]
+                });
+                let sides = (0..=height).flat_map(|row| {
+                    let row = row as f32 - 0.5;
+                    [(-0.5, row), (right, row)]
+                });
+                let latitudes = edges
+                    .chain(sides)
+                    .map(|(column, row)| self.coordinates_at(column, row).0.to_degrees());
+                let (south, north) = if *north {
+                    (latitudes.fold(90.0, f32::min), 90.0)
+                } else {
+                    (-90.0, latitudes.fold(-90.0, f32::max))
+                };
+                Bounds {
+                    south,
+                    north,
+                    west: 0.0,
+                    east: 360.0,
+                }
+            }
+        }
+    }
+
+    /// Angular size of a pixel, in radians.
+    pub fn resolution(&self) -> f32 {
+        match *self {
+            Self::Equirectangular {
+                pixels_per_degree, ..
+            } => (1.0 / pixels_per_degree).to_radians(),
+            Self::PolarStereographic { scale, radius, .. } => scale / radius,
+        }
+    }
+}
+
+impl Bounds {
+    pub fn contains_latitude(&self, latitude: f32) -> bool {
+        (self.south..=self.north).contains(&latitude.to_degrees())
+    }
+}
+
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.2}°N to {:.2}°N, {:.2}°E to {:.2}°E",
+            self.south, self.north, self.west, self.east
+        )
+    }
+}
@@ -0,0 +1,178 @@
+use super::cache::{read_bytes, read_count, read_values, write_values};
+use anyhow::bail;
+use std::io::{self, Read, Write};
+
+/// Cells above which a level is not kept, the finer levels being averaged from
+/// the samples of the map as they are sampled.
+const MAX_LEVEL_CELLS: usize = 1 << 22;
+
+/// Coarser versions of a height map, each cell of a level holding the lowest,
+/// highest and mean samples of the map it covers, holes left out.
+#[derive(Debug)]
+pub struct Pyramid {
+    /// From the finest to the coarsest.
+    levels: Vec<Level>,
+}
+
+#[derive(Debug)]
+pub struct Level {
+    /// Samples of the map covered along each side of a cell, a power of two.
+    pub factor: usize,
+    pub width: usize,
+    pub height: usize,
+    /// NaN in the cells only covering holes.
+    min: Vec<f32>,
+    max: Vec<f32>,
+    mean: Vec<f32>,
+    /// Samples of the map in the cell that are not holes.
+    count: Vec<u32>,
+}
+
+impl Pyramid {
+    /// Builds the levels of a grid of samples, `sample` giving the sample at
+    /// an index, or `None` in the holes.
+    pub fn build(width: usize, height: usize, sample: impl Fn(usize) -> Option<f32>) -> Self {
+        let mut factor = 2;
+        while width.div_ceil(factor) * height.div_ceil(factor) > MAX_LEVEL_CELLS {
+            factor *= 2;
+        }
+        let mut levels = vec![Level::from_samples(width, height, factor, sample)];
+        while let Some(level) = levels.last().filter(|level| level.width * level.height > 1) {
+            levels.push(level.coarser());
+        }
+        Self { levels }
+    }
+
+    /// Level averaging `factor` samples of the map, a power of two, or the
+    /// coarsest one, `None` if the level is finer than the ones kept.
+    pub fn level(&self, factor: usize) -> Option<&Level> {
+        self.levels
+            .iter()
+            .take_while(|level| level.factor <= factor)
+            .last()
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&(self.levels.len() as u64).to_le_bytes())?;
+        for level in &self.levels {
+            for size in [level.factor, level.width, level.height] {
+                writer.write_all(&(size as u64).to_le_bytes())?;
+            }
+            for values in [&level.min, &level.max, &level.mean] {
+                write_values(writer, values.iter().map(|value| value.to_le_bytes()))?;
+            }
+            write_values(writer, level.count.iter().map(|count| count.to_le_bytes()))?;
+        }
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> anyhow::Result<Self> {
+        let mut levels = vec![];
+        for _ in 0..u64::from_le_bytes(read_bytes(reader)?) {
+            let factor = read_count(reader)?;
+            let width = read_count(reader)?;
+            let height = read_count(reader)?;
+            let cells = width.saturating_mul(height);
+            levels.push(Level {
+                factor,
+                width,
+                height,
+                min: read_values(reader, cells, f32::from_le_bytes)?,
+                max: read_values(reader, cells, f32::from_le_bytes)?,
+                mean: read_values(reader, cells, f32::from_le_bytes)?,
+                count: read_values(reader, cells, u32::from_le_bytes)?,
+            });
+        }
+        if levels.is_empty() {
+            bail!("Pyramid without levels");
+        }
+        Ok(Self { levels })
+    }
+
+    /// Lowest and highest samples of the map, `None` if it is all holes.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let coarsest = self.levels.last()?;
+        let min = coarsest.min.iter().copied().fold(f32::NAN, f32::min);
+        let max = coarsest.max.iter().copied().fold(f32::NAN, f32::max);
+        (!min.is_nan()).then_some((min, max))
+    }
+}
+
+impl Level {
+    fn empty(factor: usize, width: usize, height: usize) -> Self {
+        let cells = width * height;
+        Self {
+            factor,
+            width,
+            height,
+            min: vec![f32::INFINITY; cells],
+            max: vec![f32::NEG_INFINITY; cells],
+            mean: vec![0.0; cells],
+            count: vec![0; cells],
+        }
+    }
+
+    fn from_samples(
+        width: usize,
+        height: usize,
+        factor: usize,
+        sample: impl Fn(usize) -> Option<f32>,
+    ) -> Self {
+        let mut level = Self::empty(factor, width.div_ceil(factor), height.div_ceil(factor));
+        for row in 0..height {
+            for column in 0..width {
+                if let Some(value) = sample(row * width + column) {
+                    let cell = row / factor * level.width + column / factor;
+                    level.add(cell, value, value, value, 1);
+                }
+            }
+        }
+        level.finish();
+        level
+    }
+
+    fn coarser(&self) -> Self {
+        let mut level = Self::empty(
+            self.factor * 2,
+            self.width.div_ceil(2),
+            self.height.div_ceil(2),
+        );
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let index = row * self.width + column;
+                let count = self.count[index];
+                if count > 0 {
+                    let cell = row / 2 * level.width + column / 2;
+                    let sum = self.mean[index] * count as f32;
+                    level.add(cell, self.min[index], self.max[index], sum, count);
+                }
+            }
+        }
+        level.finish();
+        level
+    }
+
+    /// Adds samples to a cell, their mean being summed until [`Level::finish`].
+    fn add(&mut self, cell: usize, min: f32, max: f32, sum: f32, count: u32) {
+        self.min[cell] = self.min[cell].min(min);
+        self.max[cell] = self.max[cell].max(max);
+        self.mean[cell] += sum;
+        self.count[cell] += count;
+    }
+
+    fn finish(&mut self) {
+        for cell in 0..self.count.len() {
+            if self.count[cell] > 0 {
+                self.mean[cell] /= self.count[cell] as f32;
+            } else {
+                self.min[cell] = f32::NAN;
+                self.max[cell] = f32::NAN;
+                self.mean[cell] = f32::NAN;
+            }
+        }
+    }
+
+    pub fn mean(&self, index: usize) -> f32 {
+        self.mean[index]
+    }
+}
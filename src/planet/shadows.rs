@@ -0,0 +1,115 @@
+use super::Planet;
+use crate::{player::PlayerCamera, sun::Sun};
+use bevy::prelude::*;
+
+pub const SHADOW_MAP_SIZE: usize = 4096;
+/// Smallest width, in metres, of the terrain covered by the shadow map.
+const MIN_SHADOW_EXTENT: f32 = 20000.0;
+/// Width of the terrain covered by the shadow map relative to the camera altitude.
+const SHADOW_EXTENT_FACTOR: f32 = 4.0;
+/// Offset toward the sun, in shadow map texels, keeping the lit terrain from
+/// shadowing itself.
+const DEPTH_BIAS_TEXELS: f32 = 2.0;
+
+/// Fits the sun shadow map to the terrain around the point below the camera.
+///
+/// A single shadow map cannot cover a whole planet with a useful resolution,
+/// so it shrinks as the camera gets closer to the ground.
+pub fn update_shadow_projection(
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+    planets: Query<(&Planet, &GlobalTransform)>,
+    mut suns: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+) {
+    let camera = match camera.get_single() {
+        Ok(camera) => camera.translation,
+        Err(_) => return,
+    };
+    // Altitude of the camera above the datum of a planet, and the datum radius below it.
+    let altitude = |planet: &Planet, transform: &GlobalTransform| {
+        let up = (camera - transform.translation).normalize();
+        let datum_radius = planet.datum_radius_at(transform.rotation.inverse() * up);
+        (
+            transform.translation.distance(camera) - datum_radius,
+            datum_radius,
+        )
+    };
+    let nearest = planets.iter().min_by(|(a, a_transform), (b, b_transform)| {
+        let (a_altitude, _) = altitude(a, a_transform);
+        let (b_altitude, _) = altitude(b, b_transform);
+        a_altitude.total_cmp(&b_altitude)
+    });
+
+    if let Some((planet, transform)) = nearest {
+        let up = (camera - transform.translation).normalize();
+        let (altitude, datum_radius) = altitude(planet, transform);
+        let extent = shadow_extent(altitude, planet.radius);
+        // Everything able to cast a shadow on the covered terrain, down to the
+        // deepest depression and up to the highest summit.
+        let depth = extent + (planet.max_altitude - planet.min_altitude) * planet.height_scale;
+
+        for (mut sun_transform, mut light) in suns.iter_mut() {
+            sun_transform.translation = transform.translation + up * datum_radius;
+            light.shadows_enabled = true;
+            light.shadow_projection = OrthographicProjection {
+                left: -extent / 2.0,
+                right: extent / 2.0,
+                bottom: -extent / 2.0,
+                top: extent / 2.0,
+                near: -depth,
+                far: depth,
+                ..default()
+            };
+            light.shadow_depth_bias = DEPTH_BIAS_TEXELS * extent / SHADOW_MAP_SIZE as f32;
+        }
+    }
+}
+
+/// Width of the terrain covered by the shadow map, never wider than the body,
+/// even when it is smaller than the minimum width like the moons of Mars.
+fn shadow_extent(altitude: f32, radius: f32) -> f32 {
+    (altitude * SHADOW_EXTENT_FACTOR)
+        .max(MIN_SHADOW_EXTENT)
+        .min(2.2 * radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planet::{
+        catalog::{BodyCatalog, BodyDescription, ShapeDescription},
+        ellipsoid::Ellipsoid,
+    };
+
+    fn radii(bodies: &[BodyDescription]) -> Vec<(String, f32)> {
+        bodies
+            .iter()
+            .flat_map(|body| {
+                let radius = match &body.shape {
+                    ShapeDescription::HeightMap { radius, .. } => *radius,
+                    ShapeDescription::Ellipsoid(radii) => Ellipsoid::new(*radii).mean_radius(),
+                };
+                std::iter::once((body.name.clone(), radius)).chain(radii(&body.moons))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shadow_extent_fits_every_body_of_the_catalog() {
+        let catalog: BodyCatalog = ron::from_str(include_str!("../../assets/bodies.ron")).unwrap();
+        let radii = radii(&catalog.bodies);
+        assert!(radii.iter().any(|(name, _)| name == "Deimos"));
+
+        for (name, radius) in radii {
+            for altitude in [-1e4, 0.0, 1.0, 1e3, 1e4, 1e5, 1e6, 1e7, 1e9] {
+                let extent = shadow_extent(altitude, radius);
+                assert!(
+                    extent.is_finite() && extent > 0.0,
+                    "{} at {}",
+                    name,
+                    altitude
+                );
+                assert!(extent <= 2.2 * radius, "{} at {}", name, altitude);
+            }
+        }
+    }
+}
@@ -1,5 +1,9 @@
-use super::{graphics::PlanetMaterial, Planet, PlanetMaterials};
-use bevy::prelude::*;
+use super::{
+    color_ramp::{ColorRamp, ColorRamps},
+    graphics::PlanetMaterial,
+    Planet, PlanetMaterials,
+};
+use bevy::{prelude::*, render::render_resource::AddressMode};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ColorMode {
@@ -7,15 +11,12 @@ pub enum ColorMode {
     Normal = 1,
     Inclination = 2,
     Altitude = 3,
+    Subdivision = 4,
 }
 
 impl ColorMode {
     pub fn is_custom(&self) -> bool {
-        if let Self::Real = *self {
-            false
-        } else {
-            true
-        }
+        !matches!(*self, Self::Real)
     }
 }
 
@@ -25,7 +26,8 @@ impl ColorMode {
             ColorMode::Real => ColorMode::Normal,
             ColorMode::Normal => ColorMode::Inclination,
             ColorMode::Inclination => ColorMode::Altitude,
-            ColorMode::Altitude => ColorMode::Real,
+            ColorMode::Altitude => ColorMode::Subdivision,
+            ColorMode::Subdivision => ColorMode::Real,
         };
         self.clone()
     }
@@ -37,6 +39,63 @@ pub fn cycle_color_mode(keyboard: Res<Input<KeyCode>>, mut color_mode: ResMut<Co
     }
 }
 
+pub fn cycle_color_ramp(keyboard: Res<Input<KeyCode>>, mut color_ramps: ResMut<ColorRamps>) {
+    if keyboard.just_pressed(KeyCode::F7) {
+        color_ramps.cycle();
+    }
+}
+
+pub fn update_color_ramp(
+    color_ramps: Res<ColorRamps>,
+    color_ramp_assets: Res<Assets<ColorRamp>>,
+    mut color_ramp_events: EventReader<AssetEvent<ColorRamp>>,
+    mut planet_materials: ResMut<Assets<PlanetMaterial>>,
+    planets: Query<&PlanetMaterials, With<Planet>>,
+) {
+    let active_changed = color_ramp_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+            Some(handle) == color_ramps.active()
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+
+    if color_ramps.is_changed() || active_changed {
+        let texture = color_ramps.active_texture(&color_ramp_assets);
+        for materials in planets.iter() {
+            if let Some(planet_material) = planet_materials.get_mut(materials.custom.clone()) {
+                planet_material.color_ramp = texture.clone();
+            }
+        }
+    }
+}
+
+pub fn update_albedo(
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut images: ResMut<Assets<Image>>,
+    mut standard_materials: ResMut<Assets<StandardMaterial>>,
+    planets: Query<(&Planet, Option<&PlanetMaterials>)>,
+) {
+    for event in image_events.iter() {
+        if let AssetEvent::Created { handle } = event {
+            for (planet, materials) in planets.iter() {
+                if planet.albedo.as_ref() != Some(handle) {
+                    continue;
+                }
+                // Vertices duplicated along the antimeridian have U above 1.
+                if let Some(image) = images.get_mut(handle) {
+                    image.sampler_descriptor.address_mode_u = AddressMode::Repeat;
+                }
+                let material = materials
+                    .and_then(|materials| standard_materials.get_mut(materials.standard.clone()));
+                if let Some(material) = material {
+                    material.base_color = Color::WHITE;
+                    material.base_color_texture = Some(handle.clone());
+                }
+            }
+        }
+    }
+}
+
 pub fn update_color_mode(
     mut commands: Commands,
     color_mode: Res<ColorMode>,
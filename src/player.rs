@@ -1,5 +1,5 @@
-use crate::MARS_RADIUS;
-use bevy::{input::mouse::MouseMotion, prelude::*};
+use crate::planet::Planet;
+use bevy::{input::mouse::MouseMotion, prelude::*, utils::HashMap};
 
 #[derive(Component)]
 pub struct Player;
@@ -7,17 +7,32 @@ pub struct Player;
 #[derive(Component)]
 pub struct PlayerCamera;
 
+/// Last position of the player around each body it left, by name.
+#[derive(Default)]
+pub struct PlayerPositions(HashMap<String, Transform>);
+
 const SPEED_FACTOR: f32 = 0.4;
 const ZOOM_FACTOR: f32 = 0.2;
 const ROTATION_SPEED: f32 = 0.2;
+/// Distance the player starts from the first body, relative to its radius.
+const START_DISTANCE: f32 = 4.0;
+
+impl PlayerPositions {
+    pub fn remember(&mut self, body: &Name, transform: Transform) {
+        self.0.insert(body.to_string(), transform);
+    }
+
+    fn recall(&self, body: &Name) -> Option<Transform> {
+        self.0.get(body.as_str()).copied()
+    }
+}
 
 pub fn setup_player(mut commands: Commands) {
+    commands.insert_resource(PlayerPositions::default());
     commands
         .spawn()
         .insert(Player)
-        .insert_bundle(TransformBundle::from_transform(
-            Transform::from_translation(Vec3::new(0.0, 0.0, 4.0 * MARS_RADIUS)),
-        ))
+        .insert_bundle(TransformBundle::default())
         .with_children(|children| {
             children
                 .spawn_bundle(PerspectiveCameraBundle::default())
@@ -25,12 +40,61 @@ pub fn setup_player(mut commands: Commands) {
         });
 }
 
+/// Moves the player to the frame of the next body, from the largest to the
+/// smallest, when Tab is pressed, and to the largest one at first.
+///
+/// The player goes back where it last left the new body, or stays in the same
+/// direction from it, at the same distance relative to its radius.
+pub fn focus_control(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    mut positions: ResMut<PlayerPositions>,
+    planets: Query<(Entity, &Planet, &Name)>,
+    mut player: Query<(Entity, &mut Transform, Option<&Parent>), With<Player>>,
+) {
+    let (entity, mut transform, parent) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let mut bodies: Vec<(Entity, &Planet, &Name)> = planets.iter().collect();
+    bodies.sort_by(|(_, a, _), (_, b, _)| b.radius().total_cmp(&a.radius()));
+
+    let current =
+        parent.and_then(|parent| bodies.iter().position(|(body, _, _)| *body == parent.0));
+    let next = match current {
+        None => 0,
+        Some(current) if keyboard.just_pressed(KeyCode::Tab) => (current + 1) % bodies.len(),
+        Some(_) => return,
+    };
+    let (body, planet, name) = match bodies.get(next) {
+        Some(body) => *body,
+        None => return,
+    };
+
+    if let Some(current) = current {
+        let (previous, previous_planet, previous_name) = bodies[current];
+        commands.entity(previous).remove_children(&[entity]);
+        positions.remember(previous_name, *transform);
+        let distance = transform.translation.length() * planet.radius() / previous_planet.radius();
+        transform.translation =
+            transform.translation.normalize() * distance.max(planet.bounding_radius());
+    } else {
+        *transform = Transform::from_translation(Vec3::Z * START_DISTANCE * planet.radius());
+    }
+    if let Some(position) = positions.recall(name) {
+        *transform = position;
+    }
+    commands.entity(body).push_children(&[entity]);
+    info!("Focused on {}", name);
+}
+
 pub fn player_control(
     keyboard: Res<Input<KeyCode>>,
     mouse_button: Res<Input<MouseButton>>,
     time: Res<Time>,
     mut mouse_motion: EventReader<MouseMotion>,
-    mut player: Query<&mut Transform, With<Player>>,
+    planets: Query<&Planet>,
+    mut player: Query<(&mut Transform, Option<&Parent>), With<Player>>,
     mut camera: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
 ) {
     let mut forward = 0.0;
@@ -58,8 +122,13 @@ pub fn player_control(
     let mouse_delta: Vec2 = mouse_motion.iter().map(|motion| &motion.delta).sum();
 
     let dt = time.delta_seconds();
-    let transform = &mut player.single_mut();
-    let surface_distance = transform.translation.length() - MARS_RADIUS;
+    let (mut transform, parent) = player.single_mut();
+    // The player moves in the frame of the body it is focused on.
+    let radius = match parent.and_then(|parent| planets.get(parent.0).ok()) {
+        Some(planet) => planet.radius(),
+        None => return,
+    };
+    let surface_distance = transform.translation.length() - radius;
 
     if forward != 0.0 {
         let direction = transform.rotation * Vec3::Y;
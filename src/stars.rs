@@ -0,0 +1,185 @@
+use crate::{orbit::Rotation, planet::graphics::StarsMaterial, sun::MarsClock};
+use anyhow::Context;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{mesh::PrimitiveTopology, view::NoFrustumCulling},
+    utils::BoxedFuture,
+};
+
+const BRIGHTNESS: f32 = 1.0;
+
+/// Stars of a catalog in the format of the Yale Bright Star Catalog.
+#[derive(Debug, TypeUuid)]
+#[uuid = "6a0f3d2e-91b4-4c57-8e2a-3f7d5b1c9e04"]
+pub struct StarCatalog {
+    stars: Vec<Star>,
+}
+
+#[derive(Debug)]
+struct Star {
+    /// Unit vector toward the star in the ICRF, at the J2000 epoch.
+    direction: Vec3,
+    magnitude: f32,
+}
+
+pub struct Starfield {
+    catalog: Handle<StarCatalog>,
+}
+
+#[derive(Component)]
+pub struct StarfieldSky;
+
+pub fn setup_stars(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Starfield {
+        catalog: asset_server.load("stars/bsc5.dat"),
+    });
+}
+
+pub fn spawn_starfield(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<StarCatalog>>,
+    starfield: Res<Starfield>,
+    catalogs: Res<Assets<StarCatalog>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StarsMaterial>>,
+    skies: Query<Entity, With<StarfieldSky>>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+        if *handle != starfield.catalog {
+            continue;
+        }
+        let catalog = match catalogs.get(handle) {
+            Some(catalog) => catalog,
+            None => continue,
+        };
+
+        for sky in skies.iter() {
+            commands.entity(sky).despawn();
+        }
+        commands
+            .spawn_bundle(MaterialMeshBundle {
+                mesh: meshes.add(build_mesh(catalog)),
+                material: materials.add(StarsMaterial {
+                    brightness: BRIGHTNESS,
+                }),
+                ..default()
+            })
+            .insert(StarfieldSky)
+            .insert(NoFrustumCulling);
+        info!("Spawned {} stars", catalog.stars.len());
+    }
+}
+
+/// Turns the sky as the body the scene is fixed to rotates under it.
+pub fn update_starfield(
+    clock: Res<MarsClock>,
+    primaries: Query<&Rotation>,
+    added_primaries: Query<(), Added<Rotation>>,
+    mut skies: Query<&mut Transform, With<StarfieldSky>>,
+    added: Query<(), Added<StarfieldSky>>,
+) {
+    let primary = match primaries.get_single() {
+        Ok(primary) => primary,
+        Err(_) => return,
+    };
+    if clock.is_changed() || !added.is_empty() || !added_primaries.is_empty() {
+        for mut transform in skies.iter_mut() {
+            transform.rotation = primary.scene_from_icrf(clock.julian_date_tt());
+        }
+    }
+}
+
+fn build_mesh(catalog: &StarCatalog) -> Mesh {
+    let positions: Vec<[f32; 3]> = catalog
+        .stars
+        .iter()
+        .map(|star| star.direction.into())
+        .collect();
+    let colors: Vec<[f32; 4]> = catalog
+        .stars
+        .iter()
+        .map(|star| {
+            // Divided by ten every 5 magnitudes, the square root of the
+            // hundredfold drop in flux, to keep the faint stars visible.
+            let intensity = 10f32.powf(-0.2 * star.magnitude).min(1.0);
+            [intensity, intensity, intensity, 1.0]
+        })
+        .collect();
+
+    let mut mesh = Mesh::new(PrimitiveTopology::PointList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh
+}
+
+/// Reads the J2000 positions and the visual magnitudes of the fixed width
+/// records, skipping those without a position.
+fn parse_catalog(text: &str) -> anyhow::Result<Vec<Star>> {
+    let mut stars = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let field = |start: usize, end: usize| line.get(start - 1..end).unwrap_or("").trim();
+        if field(76, 90).is_empty() {
+            continue;
+        }
+
+        let parse = |start: usize, end: usize| -> anyhow::Result<f64> {
+            field(start, end).parse().with_context(|| {
+                format!(
+                    "Invalid field at bytes {}-{} of line {}",
+                    start,
+                    end,
+                    index + 1
+                )
+            })
+        };
+        let right_ascension =
+            15.0 * (parse(76, 77)? + parse(78, 79)? / 60.0 + parse(80, 83)? / 3600.0);
+        let declination = parse(85, 86)? + parse(87, 88)? / 60.0 + parse(89, 90)? / 3600.0;
+        let declination = if field(84, 84) == "-" {
+            -declination
+        } else {
+            declination
+        };
+
+        let (right_ascension, declination) =
+            (right_ascension.to_radians(), declination.to_radians());
+        stars.push(Star {
+            direction: Vec3::new(
+                (declination.cos() * right_ascension.cos()) as f32,
+                (declination.cos() * right_ascension.sin()) as f32,
+                declination.sin() as f32,
+            ),
+            magnitude: parse(103, 107)? as f32,
+        });
+    }
+    Ok(stars)
+}
+
+#[derive(Default)]
+pub struct StarCatalogLoader;
+
+impl AssetLoader for StarCatalogLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let text = std::str::from_utf8(bytes)?;
+            let stars = parse_catalog(text)?;
+            info!("Loaded {} stars", stars.len());
+            load_context.set_default_asset(LoadedAsset::new(StarCatalog { stars }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dat"]
+    }
+}
@@ -0,0 +1,439 @@
+use crate::orbit::{HeliocentricOrbit, Orbit, Rotation};
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+const J2000: f64 = 2451545.0;
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2440587.5;
+/// Terrestrial time ahead of UTC, in seconds, since the leap second of 2017.
+const TT_MINUS_UTC: f64 = 69.184;
+/// Length of a mean Mars solar day, in Earth days.
+const SOL: f64 = 1.0274912517;
+const ASTRONOMICAL_UNIT: f32 = 149597870700.0;
+/// Illuminance of the sunlight at one astronomical unit, in lux.
+const ILLUMINANCE_AT_1_AU: f32 = 128000.0;
+/// Amplitude (degrees), period (Julian years) and phase (degrees) of the
+/// perturbations of the orbit of Mars by the other planets.
+const PERTURBATIONS: [(f64, f64, f64); 7] = [
+    (0.0071, 2.2353, 49.409),
+    (0.0057, 2.7543, 168.173),
+    (0.0039, 1.1177, 191.837),
+    (0.0037, 15.7866, 21.736),
+    (0.0021, 2.1354, 15.704),
+    (0.0020, 2.4694, 95.528),
+    (0.0018, 32.8493, 49.095),
+];
+const TIME_SCALE_FACTOR: f64 = 10.0;
+const MAX_TIME_SCALE: f64 = 1e6;
+const FONT_SIZE: f32 = 14.0;
+
+#[derive(Component)]
+pub struct Sun;
+
+#[derive(Component)]
+pub struct ClockLabel;
+
+/// Simulated time, as a Julian date in UTC, driving the position of the sun.
+pub struct MarsClock {
+    julian_date: f64,
+    start: f64,
+    time_scale: f64,
+    paused: bool,
+    /// UTC date being typed to jump to, parsed like `--date`.
+    date_entry: Option<String>,
+}
+
+/// Date and season on Mars shown by the clock, following Allison & McEwen (2000).
+pub struct SolarPosition {
+    pub mars_sol_date: f64,
+    /// Areocentric longitude of the sun, in degrees, 0 at the northern spring equinox.
+    pub solar_longitude: f64,
+}
+
+impl MarsClock {
+    pub fn new(julian_date: f64) -> Self {
+        Self {
+            julian_date,
+            start: julian_date,
+            time_scale: 1.0,
+            paused: false,
+            date_entry: None,
+        }
+    }
+
+    /// Starts at the UTC date following `--date` on the command line, or now.
+    pub fn from_args() -> Self {
+        let mut args = std::env::args().skip_while(|arg| arg != "--date").skip(1);
+        let julian_date = match args.next().map(|date| parse_utc_date(&date)) {
+            Some(Ok(julian_date)) => julian_date,
+            Some(Err(err)) => {
+                error!("Invalid start date: {:?}", err);
+                now()
+            }
+            None => now(),
+        };
+        Self::new(julian_date)
+    }
+
+    /// Julian date in terrestrial time, the time scale of the ephemerides.
+    pub fn julian_date_tt(&self) -> f64 {
+        self.julian_date + TT_MINUS_UTC / SECONDS_PER_DAY
+    }
+
+    pub fn solar_position(&self) -> SolarPosition {
+        solar_position(self.julian_date)
+    }
+}
+
+pub fn setup_sun(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let clock = MarsClock::from_args();
+    commands
+        .spawn_bundle(DirectionalLightBundle { ..default() })
+        .insert(Sun);
+    commands.insert_resource(clock);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: FONT_SIZE,
+                    color: Color::WHITE,
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(ClockLabel);
+}
+
+pub fn clock_control(keyboard: Res<Input<KeyCode>>, mut clock: ResMut<MarsClock>) {
+    if keyboard.just_pressed(KeyCode::T) {
+        clock.paused = !clock.paused;
+    }
+    if keyboard.just_pressed(KeyCode::Equals) {
+        clock.time_scale = (clock.time_scale * TIME_SCALE_FACTOR).min(MAX_TIME_SCALE);
+    }
+    if keyboard.just_pressed(KeyCode::Minus) {
+        clock.time_scale = (clock.time_scale / TIME_SCALE_FACTOR).max(1.0);
+    }
+    // Steps of one Mars hour, to pick the time of day.
+    if keyboard.just_pressed(KeyCode::Key0) {
+        clock.julian_date += SOL / 24.0;
+    }
+    if keyboard.just_pressed(KeyCode::Key9) {
+        clock.julian_date -= SOL / 24.0;
+    }
+    if keyboard.just_pressed(KeyCode::Back) {
+        clock.julian_date = clock.start;
+    }
+}
+
+/// J starts typing a UTC date, entered with return and dropped with escape,
+/// the keys going to the date alone meanwhile.
+pub fn date_entry_control(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut clock: ResMut<MarsClock>,
+) {
+    let typed: String = characters.iter().map(|character| character.char).collect();
+    if clock.date_entry.is_none() {
+        if keyboard.just_pressed(KeyCode::J) {
+            clock.date_entry = Some(String::new());
+            consume_keys(&mut keyboard);
+        }
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::Escape) {
+        clock.date_entry = None;
+    } else if keyboard.just_pressed(KeyCode::Return) {
+        let entry = clock.date_entry.take().unwrap_or_default();
+        match parse_utc_date(&entry) {
+            Ok(julian_date) => clock.julian_date = julian_date,
+            Err(err) => {
+                error!("Invalid date {:?}: {:?}", entry, err);
+                clock.date_entry = Some(entry);
+            }
+        }
+    } else if keyboard.just_pressed(KeyCode::Back) {
+        if let Some(entry) = clock.date_entry.as_mut() {
+            entry.pop();
+        }
+    } else if let Some(entry) = clock.date_entry.as_mut() {
+        entry.extend(
+            typed
+                .chars()
+                .map(|character| character.to_ascii_uppercase())
+                .filter(|character| character.is_ascii_digit() || "-:.TZ".contains(*character)),
+        );
+    }
+    consume_keys(&mut keyboard);
+}
+
+/// Hides the keys from the other systems, releasing the held ones without
+/// them noticing, so they do not keep moving the camera while typing.
+fn consume_keys(keyboard: &mut Input<KeyCode>) {
+    let pressed: Vec<_> = keyboard.get_pressed().copied().collect();
+    for key in pressed {
+        keyboard.reset(key);
+    }
+    keyboard.clear();
+}
+
+pub fn advance_clock(time: Res<Time>, mut clock: ResMut<MarsClock>) {
+    if !clock.paused {
+        clock.julian_date += time.delta_seconds_f64() * clock.time_scale / SECONDS_PER_DAY;
+    }
+}
+
+/// Lights the scene from the sun, as seen from the body it is fixed to, and
+/// shows the date.
+pub fn update_sun(
+    clock: Res<MarsClock>,
+    bodies: Query<(&Rotation, &HeliocentricOrbit)>,
+    added: Query<(), Added<HeliocentricOrbit>>,
+    mut suns: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+    mut labels: Query<&mut Text, With<ClockLabel>>,
+) {
+    if !clock.is_changed() && added.is_empty() {
+        return;
+    }
+
+    if let Ok((rotation, orbit)) = bodies.get_single() {
+        let (direction, distance) = sunlight(rotation, &orbit.0, clock.julian_date_tt());
+        for (mut transform, mut light) in suns.iter_mut() {
+            transform.rotation = Transform::default().looking_at(direction, Vec3::Y).rotation;
+            light.illuminance = ILLUMINANCE_AT_1_AU / distance.powi(2);
+        }
+    }
+
+    let position = clock.solar_position();
+    for mut text in labels.iter_mut() {
+        text.sections[0].value = format!(
+            "{} UTC  MSD {:.3}  Ls {:.1}°  x{}{}",
+            format_utc_date(clock.julian_date),
+            position.mars_sol_date,
+            position.solar_longitude,
+            clock.time_scale,
+            if clock.paused { " (paused)" } else { "" }
+        );
+        if let Some(entry) = &clock.date_entry {
+            text.sections[0].value += &format!("\nGo to UTC date: {}_", entry);
+        }
+    }
+}
+
+/// Direction of the sunlight in the scene, fixed to the body with `rotation`
+/// going around the sun on `orbit`, and its distance from the sun in
+/// astronomical units.
+fn sunlight(rotation: &Rotation, orbit: &Orbit, julian_date_tt: f64) -> (Vec3, f32) {
+    let from_sun = orbit.position(julian_date_tt);
+    (
+        rotation.scene_from_icrf(julian_date_tt) * from_sun.normalize(),
+        from_sun.length() / ASTRONOMICAL_UNIT,
+    )
+}
+
+pub fn solar_position(julian_date: f64) -> SolarPosition {
+    let julian_date_tt = julian_date + TT_MINUS_UTC / SECONDS_PER_DAY;
+    let days = julian_date_tt - J2000;
+
+    let mean_anomaly = (19.3871 + 0.52402073 * days).to_radians();
+    let fictitious_mean_sun = 270.3871 + 0.524038496 * days;
+    let perturbations: f64 = PERTURBATIONS
+        .iter()
+        .map(|(amplitude, period, phase)| {
+            amplitude * (0.985626 * days / period + phase).to_radians().cos()
+        })
+        .sum();
+    let equation_of_center = (10.691 + 3.0e-7 * days) * mean_anomaly.sin()
+        + 0.623 * (2.0 * mean_anomaly).sin()
+        + 0.050 * (3.0 * mean_anomaly).sin()
+        + 0.005 * (4.0 * mean_anomaly).sin()
+        + 0.0005 * (5.0 * mean_anomaly).sin()
+        + perturbations;
+    let solar_longitude = (fictitious_mean_sun + equation_of_center).rem_euclid(360.0);
+
+    let mars_sol_date = (julian_date_tt - 2451549.5) / SOL + 44796.0 - 0.0009626;
+
+    SolarPosition {
+        mars_sol_date,
+        solar_longitude,
+    }
+}
+
+fn now() -> f64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default();
+    UNIX_EPOCH_JULIAN_DATE + seconds / SECONDS_PER_DAY
+}
+
+/// Parses a UTC date such as `2021-02-18` or `2021-02-18T20:55:00Z` into a Julian date.
+pub fn parse_utc_date(date: &str) -> anyhow::Result<f64> {
+    let date = date.trim_end_matches('Z');
+    let (day, time) = date.split_once('T').unwrap_or((date, "00:00:00"));
+
+    let day: Vec<i64> = day
+        .split('-')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid day {}", day))?;
+    let time: Vec<f64> = time
+        .split(':')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid time {}", time))?;
+    if day.len() != 3 || !(1..=12).contains(&day[1]) || !(1..=31).contains(&day[2]) {
+        bail!("Expected a YYYY-MM-DD day, got {:?}", day);
+    }
+    if time.is_empty() || time.len() > 3 {
+        bail!("Expected a HH:MM:SS time, got {:?}", time);
+    }
+
+    let seconds = time
+        .iter()
+        .zip([3600.0, 60.0, 1.0])
+        .map(|(t, s)| t * s)
+        .sum::<f64>();
+    let days = days_from_civil(day[0], day[1], day[2]) as f64 + seconds / SECONDS_PER_DAY;
+    Ok(UNIX_EPOCH_JULIAN_DATE + days)
+}
+
+fn format_utc_date(julian_date: f64) -> String {
+    // Rounded to the second first, so the dates typed in do not show a minute early.
+    let seconds = ((julian_date - UNIX_EPOCH_JULIAN_DATE) * SECONDS_PER_DAY).round() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY as i64));
+    let minutes = seconds.rem_euclid(SECONDS_PER_DAY as i64) / 60;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Days since the Unix epoch of a day of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planet::catalog::BodyCatalog;
+
+    #[test]
+    fn allison_mcewen_example() {
+        // Example of Allison & McEwen (2000), whose TT - UTC of 64.184 s in
+        // 2000 moves the Mars sol date by less than 1e-4.
+        let position = solar_position(parse_utc_date("2000-01-06T00:00:00Z").unwrap());
+        assert!(
+            (position.mars_sol_date - 44795.9998).abs() < 1e-4,
+            "{}",
+            position.mars_sol_date
+        );
+        assert!(
+            (position.solar_longitude - 277.19).abs() < 0.01,
+            "{}",
+            position.solar_longitude
+        );
+    }
+
+    #[test]
+    fn sunlight_on_mars_follows_its_seasons() {
+        // Subsolar latitude of Allison & McEwen (2000) from the solar
+        // longitude and the obliquity of Mars.
+        let catalog: BodyCatalog = ron::from_str(include_str!("../assets/bodies.ron")).unwrap();
+        let mars = &catalog.bodies[0];
+        let (rotation, orbit) = (mars.rotation.as_ref(), mars.heliocentric_orbit.as_ref());
+        for date in ["2000-01-06", "2010-03-01", "2021-02-18", "2024-07-01"] {
+            let julian_date = parse_utc_date(date).unwrap();
+            let ls = solar_position(julian_date).solar_longitude.to_radians();
+            let declination =
+                (25.19f64.to_radians().sin() * ls.sin()).asin().to_degrees() + 0.25 * ls.sin();
+
+            let (direction, distance) = sunlight(
+                rotation.unwrap(),
+                orbit.unwrap(),
+                julian_date + TT_MINUS_UTC / SECONDS_PER_DAY,
+            );
+            let latitude = (-direction.y).asin().to_degrees() as f64;
+            assert!(
+                (latitude - declination).abs() < 0.5,
+                "{}: {} {}",
+                date,
+                latitude,
+                declination
+            );
+            assert!((1.38..1.67).contains(&distance), "{}: {}", date, distance);
+        }
+    }
+
+    #[test]
+    fn utc_date_round_trip() {
+        for date in ["2000-01-06 00:00", "2021-02-18 20:55", "1969-07-20 20:17"] {
+            let julian_date = parse_utc_date(&date.replace(' ', "T")).unwrap();
+            assert_eq!(format_utc_date(julian_date), date);
+        }
+        assert_eq!(
+            parse_utc_date("2021-02-18").unwrap(),
+            parse_utc_date("2021-02-18T00:00:00Z").unwrap()
+        );
+        assert_eq!(parse_utc_date("2000-01-01T12:00:00Z").unwrap(), J2000);
+    }
+
+    #[test]
+    fn malformed_utc_dates() {
+        for date in [
+            "",
+            "yesterday",
+            "2021-02",
+            "2021-13-01",
+            "2021-02-00",
+            "2021-02-18T",
+            "2021-02-18T20:55:00:00",
+            "2021-02-18T20h55",
+        ] {
+            assert!(parse_utc_date(date).is_err(), "{:?}", date);
+        }
+    }
+}